@@ -1,10 +1,10 @@
 use crate::board::LogicVal::{False, Poss, True};
-use crate::constraints::Constraint;
+use crate::constraints::{Constraint, Contradiction};
 use std::borrow::BorrowMut;
 use std::collections::{HashMap, HashSet};
 use std::collections::hash_map::RandomState;
 use std::fmt::{Debug, Display, Formatter};
-use std::hash::{BuildHasher, Hash, Hasher};
+use std::hash::{Hash, Hasher};
 
 // SudokuStandard
 #[derive(Clone, Copy, Debug)]
@@ -17,7 +17,6 @@ pub enum LogicVal {
 // T is the identifier/ value of each node
 // Making it unique is good for my case
 pub struct GraphNode<T> {
-    val: T,
     conn: Vec<T>,
 }
 
@@ -26,23 +25,14 @@ type Graph<'a, T> = HashMap<T, GraphNode<T>>;
 impl PartialEq<LogicVal> for &LogicVal {
     fn eq(&self, other: &LogicVal) -> bool {
         match self {
-            True => match other {
-                True => true,
-                _ => false,
-            },
-            Poss => match other {
-                Poss => true,
-                _ => false,
-            },
-            False => match other {
-                False => true,
-                _ => false,
-            },
+            True => matches!(other, True),
+            Poss => matches!(other, Poss),
+            False => matches!(other, False),
         }
     }
 }
 
-pub struct Puzzle<T : Eq + Hash + Enumerable + Clone, S : Board<T>> {
+pub struct Puzzle<T : Eq + Hash + Clone, S : Board<T>> {
     pub board: S,
     pub constraints: Vec<Box<dyn Constraint<T,S>>>,
     hasher : RandomState,
@@ -55,69 +45,54 @@ pub struct Board<S> {
 }
  */
 
-pub trait Enumerable {
-    fn positions() -> Vec<Self> where Self: Sized;
-}
-
-pub struct Tuple3D<const MAX: usize> {
+/// (row, col, digit), all starting at 0
+pub struct Tuple3D {
     pub(crate) pos : (usize, usize, usize)
 }
 
-impl<const MAX: usize> From<(usize, usize, usize)> for Tuple3D<MAX> {
+impl From<(usize, usize, usize)> for Tuple3D {
     fn from(v: (usize, usize, usize)) -> Self {
         Tuple3D {pos : v}
     }
 }
 
-impl<const SIZE: usize> Hash for Tuple3D<SIZE> {
+impl Hash for Tuple3D {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.pos.hash(state);
     }
 }
 
-impl<const SIZE: usize>  PartialEq<Self> for Tuple3D<SIZE> {
+impl PartialEq<Self> for Tuple3D {
     fn eq(&self, other: &Self) -> bool {
         self.pos == other.pos
     }
 }
 
-impl<const SIZE: usize> Eq for Tuple3D<SIZE> {
+impl Eq for Tuple3D {
 
 }
 
-impl<const MAX: usize> Enumerable for Tuple3D<MAX> {
-    fn positions() -> Vec<Self> {
-        let mut ret = Vec::new();
-        for x in 0..MAX {
-            for y in 0..MAX {
-                for z in 0..MAX {
-                    ret.push(Tuple3D::<MAX>::from((x, y, z)));
-                }
-            }
-        }
-        ret
-    }
-}
-
-impl<const MAX:usize> Clone for Tuple3D<MAX> {
+impl Clone for Tuple3D {
     fn clone(&self) -> Self {
-        Self{pos: self.pos}
-    }
-
-    fn clone_from(&mut self, source: &Self) where Self: {
-        self.pos = source.pos;
+        *self
     }
 }
 
-impl<const MAX: usize> Copy for Tuple3D<MAX> {
+impl Copy for Tuple3D {
 
 }
 
-pub trait Board<T : Eq + Hash + Enumerable> {
+pub trait Board<T : Eq + Hash> {
     fn getm(&mut self, x: &T) -> &mut LogicVal;
 
     fn get(&self, x: &T) -> &LogicVal;
 
+    /// Every position on the board
+    fn positions(&self) -> Vec<T>;
+
+    /// Every position in the same cell as `v`, one of which has to be True
+    fn cell(&self, v: &T) -> Vec<T>;
+
     fn num_solved(&self) -> usize;
 
     fn max_solved(&self) -> usize;
@@ -125,24 +100,47 @@ pub trait Board<T : Eq + Hash + Enumerable> {
     fn clone(&self) -> Self;
 }
 
-pub struct SdkBoard<const SIZE: usize> {
+/// A board of rows, columns and digits, with its size known only at runtime
+pub trait Grid {
+    fn rows(&self) -> usize;
+
+    fn cols(&self) -> usize;
+
+    /// Number of digits a cell can hold
+    fn digits(&self) -> usize;
+
+    /// The number a digit stands for, for rules that add digits up
+    fn value(&self, digit: usize) -> i64;
+}
+
+pub struct SdkBoard {
+    pub rows: usize,
+    pub cols: usize,
+    /// Label of each digit, in order
+    pub labels: Vec<String>,
     pub data: Vec<LogicVal>,
 }
 
-impl<const SIZE: usize> SdkBoard<SIZE> {
+impl SdkBoard {
+    pub fn new(rows: usize, cols: usize, labels: Vec<String>) -> Self {
+        SdkBoard {
+            rows,
+            cols,
+            data: vec![Poss; rows * cols * labels.len()],
+            labels,
+        }
+    }
+
     pub fn serialize(&self) -> Option<String> {
         let mut s = String::from("");
-        for x in 0..SIZE {
-            for y in 0..SIZE {
+        for x in 0..self.rows {
+            for y in 0..self.cols {
                 let mut has_digit = false;
-                for z in 0..SIZE {
-                    match self.get(&Tuple3D::from((x, y, z))) {
-                        True => {
-                            s += &((z + 1).to_string() + ",");
-                            has_digit = true;
-                            break;
-                        }
-                        _ => {}
+                for z in 0..self.labels.len() {
+                    if self.get(&Tuple3D::from((x, y, z))) == True {
+                        s += &(self.labels[z].clone() + ",");
+                        has_digit = true;
+                        break;
                     }
                 }
                 if !has_digit {
@@ -154,25 +152,42 @@ impl<const SIZE: usize> SdkBoard<SIZE> {
     }
 }
 
-impl<const SIZE: usize> Board<Tuple3D<SIZE>> for SdkBoard<SIZE> {
-    fn getm(&mut self, v: &Tuple3D<SIZE>) -> &mut LogicVal {
+impl Board<Tuple3D> for SdkBoard {
+    fn getm(&mut self, v: &Tuple3D) -> &mut LogicVal {
         let (x,y,z) = v.pos;
-        self.data[SIZE*SIZE*x+SIZE*y+z].borrow_mut()
+        let digits = self.labels.len();
+        self.data[(self.cols*x+y)*digits+z].borrow_mut()
     }
 
-    fn get(&self, v: &Tuple3D<SIZE>) -> &LogicVal {
+    fn get(&self, v: &Tuple3D) -> &LogicVal {
         let (x,y,z) = v.pos;
-        &self.data[SIZE * SIZE * x + SIZE * y + z]
+        &self.data[(self.cols * x + y) * self.labels.len() + z]
+    }
+
+    fn positions(&self) -> Vec<Tuple3D> {
+        let mut ret = Vec::new();
+        for x in 0..self.rows {
+            for y in 0..self.cols {
+                for z in 0..self.labels.len() {
+                    ret.push(Tuple3D::from((x, y, z)));
+                }
+            }
+        }
+        ret
+    }
+
+    fn cell(&self, v: &Tuple3D) -> Vec<Tuple3D> {
+        let (x, y, _) = v.pos;
+        (0..self.labels.len()).map(|z| Tuple3D::from((x, y, z))).collect()
     }
 
     fn num_solved(&self) -> usize {
         let mut num = 0;
-        for x in 0..SIZE {
-            for y in 0..SIZE {
-                for z in 0..SIZE {
-                    match self.get(&Tuple3D::from((x, y, z))) {
-                        True => {num += 1}
-                        _ => {}
+        for x in 0..self.rows {
+            for y in 0..self.cols {
+                for z in 0..self.labels.len() {
+                    if self.get(&Tuple3D::from((x, y, z))) == True {
+                        num += 1;
                     }
                 }
             }
@@ -181,15 +196,64 @@ impl<const SIZE: usize> Board<Tuple3D<SIZE>> for SdkBoard<SIZE> {
     }
 
     fn max_solved(&self) -> usize {
-        SIZE*SIZE
+        self.rows*self.cols
     }
 
     fn clone(&self) -> Self {
-        SdkBoard{ data: self.data.clone()}
+        SdkBoard{ rows: self.rows, cols: self.cols, labels: self.labels.clone(), data: self.data.clone()}
+    }
+}
+
+impl Grid for SdkBoard {
+    fn rows(&self) -> usize {
+        self.rows
+    }
+
+    fn cols(&self) -> usize {
+        self.cols
+    }
+
+    fn digits(&self) -> usize {
+        self.labels.len()
     }
+
+    /// Labels that aren't numbers count up from 1
+    fn value(&self, digit: usize) -> i64 {
+        self.labels[digit].parse().unwrap_or(digit as i64 + 1)
+    }
+}
+
+/// How many solutions a puzzle has, as far as it was counted
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SolutionCount {
+    Exactly(usize),
+    /// Counting stopped at the limit
+    AtLeast(usize),
 }
 
-impl<T : Eq + Hash + Enumerable + Clone, S : Board<T>> Puzzle<T, S> {
+impl Display for SolutionCount {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SolutionCount::Exactly(n) => write!(f, "{}", n),
+            SolutionCount::AtLeast(n) => write!(f, "at least {}", n),
+        }
+    }
+}
+
+pub struct Solutions<S> {
+    pub count: SolutionCount,
+    /// The solutions that were found
+    pub examples: Vec<S>,
+}
+
+pub struct Guessed<S> {
+    /// The first solution found, if there is one
+    pub solution: Option<S>,
+    /// How many guesses the search made, counting ones it backed out of
+    pub guesses: usize,
+}
+
+impl<T : Eq + Hash + Clone, S : Board<T>> Puzzle<T, S> {
     /*
     pub(crate) fn init(size: usize) -> Puzzle<T, S>;
     {
@@ -257,46 +321,127 @@ impl<T : Eq + Hash + Enumerable + Clone, S : Board<T>> Puzzle<T, S> {
     }
      */
 
-    pub(crate) fn solve_simple(&mut self, slow: bool) -> bool {
+    pub(crate) fn solve_simple(&mut self, slow: bool) -> Result<bool, Contradiction<T>> {
         let mut did = false;
         for con in &self.constraints {
-            if !slow {
-                did = con.apply(&mut self.board) || did;
+            if slow && did {
+                break;
             }
-            else {
-                did = did || con.apply(&mut self.board);
+            did = con.apply(&mut self.board)? || did;
+        }
+        Ok(did)
+    }
+
+    /// Applies every constraint until nothing changes
+    pub(crate) fn propagate(&mut self) -> Result<(), Contradiction<T>> {
+        while self.solve_simple(false)? {}
+        Ok(())
+    }
+
+    /// Counts solutions by filling in what the constraints force and guessing when they stall.
+    /// Stops once `limit` solutions are found, so a limit of 2 is enough to check uniqueness
+    pub(crate) fn count_solutions(&mut self, limit: usize) -> Solutions<S> {
+        assert!(limit >= 1, "a solution limit has to be at least 1");
+        self.count_solutions_within(limit, usize::MAX).unwrap()
+    }
+
+    /// Like `count_solutions`, but gives up with None after `max_guesses` guesses
+    pub(crate) fn count_solutions_within(&mut self, limit: usize, max_guesses: usize) -> Option<Solutions<S>> {
+        let backup = self.board.clone();
+        let mut examples = vec![];
+        let mut guesses = 0;
+        self.search(limit, max_guesses, &mut examples, &mut guesses);
+        self.board = backup;
+        if guesses > max_guesses {
+            return None;
+        }
+        let count = if examples.len() >= limit {
+            SolutionCount::AtLeast(examples.len())
+        } else {
+            SolutionCount::Exactly(examples.len())
+        };
+        Some(Solutions { count, examples })
+    }
+
+    /// Finishes the puzzle by guessing whenever the constraints stall, backing out of guesses
+    /// that break a constraint. The board is left solved if there is a solution
+    pub(crate) fn solve_backtrack(&mut self) -> Guessed<S> {
+        let backup = self.board.clone();
+        let mut found = vec![];
+        let mut guesses = 0;
+        self.search(1, usize::MAX, &mut found, &mut guesses);
+        let solution = found.pop();
+        self.board = match &solution {
+            Some(board) => board.clone(),
+            None => backup,
+        };
+        Guessed { solution, guesses }
+    }
+
+    /// The candidates of the unsolved cell with the fewest left
+    pub(crate) fn most_constrained(&self) -> Option<Vec<T>> {
+        let mut best: Option<Vec<T>> = None;
+        for v in self.board.positions() {
+            if self.board.get(&v) != Poss {
+                continue;
+            }
+            let cands: Vec<T> = self.board.cell(&v).into_iter().filter(|c| self.board.get(c) == Poss).collect();
+            if best.as_ref().is_none_or(|b| cands.len() < b.len()) {
+                best = Some(cands);
+            }
+        }
+        best
+    }
+
+    /// Adds every solution reachable from the current board to `found`, up to `limit`,
+    /// stopping early once there have been more than `max_guesses` guesses
+    fn search(&mut self, limit: usize, max_guesses: usize, found: &mut Vec<S>, guesses: &mut usize) {
+        if found.len() >= limit || *guesses > max_guesses || self.propagate().is_err() {
+            return;
+        }
+        match self.most_constrained() {
+            None => found.push(self.board.clone()),
+            Some(cands) => {
+                let backup = self.board.clone();
+                for v in cands {
+                    if found.len() >= limit || *guesses > max_guesses {
+                        break;
+                    }
+                    *guesses += 1;
+                    *(self.board.getm(&v)) = True;
+                    self.search(limit, max_guesses, found, guesses);
+                    self.board = backup.clone();
+                }
             }
         }
-        did
     }
 
     /// One iteration of attempting to solve the puzzle
-    pub(crate) fn solve(&mut self, slow: bool) -> bool {
-        match self.solve_simple(slow) {
-            true => true,
+    pub(crate) fn solve(&mut self, slow: bool) -> Result<bool, Contradiction<T>> {
+        match self.solve_simple(slow)? {
+            true => Ok(true),
             false => {
                 if self.board.num_solved() == self.board.max_solved() {
-                    return false;
+                    return Ok(false);
                 }
-                eprintln!("Try loops");
-                self.rem_odd_loops(None, slow).0
+                Ok(self.rem_odd_loops(None, slow).0)
             }
         }
     }
 
     /// Gets the next cell that can be filled
     pub(crate) fn weak_hint(&mut self) -> Option<T> {
-        let mut backup = self.board.clone();
+        let backup = self.board.clone();
         let start = self.board.num_solved();
         while self.board.num_solved() == start {
-            let did = self.solve(true);
+            let did = self.solve(true).unwrap_or(false);
             if !did {
                 self.board = backup;
                 //return String::from("No hint found");
                 return None;
             }
         }
-        for v in T::positions() {
+        for v in self.board.positions() {
             if self.board.get(&v) == True && backup.get(&v) == Poss {
                 //let row = char::from(65 + (x as u8));
                 self.board = backup;
@@ -310,15 +455,15 @@ impl<T : Eq + Hash + Enumerable + Clone, S : Board<T>> Puzzle<T, S> {
 
     /// Gets a hint on what cell to look at to fill and all cells to consider when removing it
     pub(crate) fn strong_hint(&mut self) -> Vec<T> {
-        let mut backup = self.board.clone();
+        let backup = self.board.clone();
         let start = self.board.num_solved();
         while self.board.num_solved() == start {
-            let did = self.solve_simple(false);
+            let did = self.solve_simple(false).unwrap_or(false);
             if !did {
                 break;
             }
         }
-        for v in T::positions() {
+        for v in self.board.positions() {
             if self.board.get(&v) == True && backup.get(&v) == Poss {
                 //let row = char::from(65 + (x as u8));
                 self.board = backup;
@@ -328,9 +473,9 @@ impl<T : Eq + Hash + Enumerable + Clone, S : Board<T>> Puzzle<T, S> {
         }
         let mut cycles = vec![];
         while self.board.num_solved() == start {
-            let (l, v) = self.find_odd_loops(None, true);
+            let (_, v) = self.find_odd_loops(None, true);
             cycles.extend(v);
-            if !self.solve(true) {
+            if !self.solve(true).unwrap_or(false) {
                 self.board = backup;
                 eprintln!("Not able to solve");
                 //return String::from("No hint found.");
@@ -338,7 +483,7 @@ impl<T : Eq + Hash + Enumerable + Clone, S : Board<T>> Puzzle<T, S> {
             }
         }
 
-        for pos in T::positions() {
+        for pos in self.board.positions() {
             if self.board.get(&pos) == True && backup.get(&pos) == Poss {
                 //let row = char::from(65 + (x as u8));
                 //eprintln!("{},{},{}", x, y, z);
@@ -429,21 +574,15 @@ impl<T : Eq + Hash + Enumerable + Clone, S : Board<T>> Puzzle<T, S> {
      */
 
     /// Get the graph of weak links for the puzzle
-    fn graph(&self) -> Graph<T> {
+    fn graph(&self) -> Graph<'_, T> {
         let mut graph: Graph<T> = HashMap::with_hasher(self.hasher.clone());
-        for pos in T::positions() {
+        for pos in self.board.positions() {
             if self.board.get(&pos) == Poss {
-                let mut node = GraphNode {
-                    val: (&pos).clone(),
-                    conn: vec![],
-                };
+                let mut node = GraphNode { conn: vec![] };
                 for i in self.get_weaks(&pos) {
-                    match graph.get_mut(&i) {
-                        Some(n) => {
-                            node.conn.push(i);
-                            n.conn.push(pos.clone());
-                        }
-                        None => {}
+                    if let Some(n) = graph.get_mut(&i) {
+                        node.conn.push(i);
+                        n.conn.push(pos.clone());
                     }
                 }
                 graph.insert(pos, node);
@@ -453,63 +592,58 @@ impl<T : Eq + Hash + Enumerable + Clone, S : Board<T>> Puzzle<T, S> {
         graph
     }
 
-    /// Get the graph of strong links for the puzzle
-    fn graph_strong(&self) -> Graph<T> {
-        let mut weak_graph = self.graph();
-        let mut to_rem = Vec::new();
-        for start in weak_graph.values() {
-            for long1 in &start.conn {
-                for long2 in &(weak_graph.get(long1).unwrap().conn) {
-                    if start.conn.contains(long2) {
-                        //eprintln!("({},{},{}),({},{},{}),({},{},{})", start.val.0, start.val.1, start.val.2, long1.0, long1.1, long1.2, long2.0, long2.1, long2.2);
-                        to_rem.push((start.val.clone(), long1.to_owned()));
+    /// Get the graph of strong links for the puzzle: two positions are linked when they are
+    /// the last two left of a set that needs one of them, like a cell with two candidates.
+    /// A weak link alone says nothing about a strong one, since rules like anti-knight only
+    /// keep digits apart without needing either
+    fn graph_strong(&self) -> Graph<'_, T> {
+        let mut graph: Graph<T> = HashMap::with_hasher(self.hasher.clone());
+        for pos in self.board.positions() {
+            if self.board.get(&pos) == Poss {
+                graph.insert(pos, GraphNode { conn: vec![] });
+            }
+        }
+        for con in &self.constraints {
+            for set in con.strong_sets(&self.board) {
+                if set.iter().any(|v| self.board.get(v) == True) {
+                    continue;
+                }
+                let left: Vec<&T> = set.iter().filter(|v| self.board.get(v) == Poss).collect();
+                if let [a, b] = left[..] {
+                    if !graph[a].conn.contains(b) {
+                        graph.get_mut(a).unwrap().conn.push(b.clone());
+                        graph.get_mut(b).unwrap().conn.push(a.clone());
                     }
                 }
             }
         }
-        for (s, e) in to_rem {
-            //eprintln!("({},{},{}),({},{},{})", s.0, s.1, s.2, e.0, e.1, e.2);
-            weak_graph.get_mut(&s).unwrap().conn.retain(|x| *x != e);
-            weak_graph.get_mut(&e).unwrap().conn.retain(|x| *x != s);
-        }
-        weak_graph
+        graph
     }
 
     /// Basically just inference chain algorithm
     /// Returns true if it did something
     /// @param max: max number of iterations to try
     /// @param slow: whether to do only one removal per call. Is not for efficiency
-    fn rem_odd_loops(&mut self, max: Option<usize>, slow: bool) -> (bool, usize) {
-        let m = match max {
-            None => 20,
-            Some(e) => e,
-        };
+    pub(crate) fn rem_odd_loops(&mut self, max: Option<usize>, slow: bool) -> (bool, usize) {
+        let m = max.unwrap_or(20);
 
         let mut min = m + 1;
         let mut to_rem = vec![];
 
         let wg = self.graph();
         let sg = self.graph_strong();
-        let mut wsum = 0;
-        for j in wg.values() {
-            wsum += j.conn.len();
-        }
-        let mut ssum = 0;
-        for j in sg.values() {
-            ssum += j.conn.len();
-        }
-        eprintln!("ln:{},{}", ssum / 2, wsum / 2);
         let mut succ = false;
-        for (spos, s) in &wg {
+        for spos in wg.keys() {
             let mut visited = HashSet::with_hasher(self.hasher.clone());
             let mut to_visit = HashSet::with_hasher(self.hasher.clone());
             to_visit.insert(spos.clone());
             let mut need_strong = false;
-            'findloop: for i in 0..min {
-                let (graph, check_graph, csum) = if need_strong {
-                    (&sg, &wg, wsum)
+            let limit = min;
+            'findloop: for i in 0..limit {
+                let (graph, check_graph) = if need_strong {
+                    (&sg, &wg)
                 } else {
-                    (&wg, &sg, ssum)
+                    (&wg, &sg)
                 };
                 need_strong = !need_strong;
                 let mut new_visit = HashSet::with_hasher(self.hasher.clone());
@@ -533,7 +667,7 @@ impl<T : Eq + Hash + Enumerable + Clone, S : Board<T>> Puzzle<T, S> {
                     }
                 }
                 to_visit.drain();
-                let s2 = HashSet::from_iter(new_visit.iter().map(|x| x.clone()));
+                let s2 = HashSet::from_iter(new_visit.iter().cloned());
                 for v in &new_visit {
                     let s1 = HashSet::<T>::from_iter(
                         check_graph
@@ -541,10 +675,10 @@ impl<T : Eq + Hash + Enumerable + Clone, S : Board<T>> Puzzle<T, S> {
                             .unwrap()
                             .conn
                             .iter()
-                            .map(|x| x.clone()),
+                            .cloned(),
                     );
                     let inter: HashSet<_> = s1.intersection(&s2).collect();
-                    if inter.len() > 0 {
+                    if !inter.is_empty() {
                         //eprintln!("From {}:({},{},{}), {}", i, v.0,v.1,v.2, csum/2);
                         /*
                         for i in inter {
@@ -565,7 +699,6 @@ impl<T : Eq + Hash + Enumerable + Clone, S : Board<T>> Puzzle<T, S> {
                 to_visit = new_visit;
             }
         }
-        eprintln!("{}", min);
         for (i, pos) in to_rem {
             if i <= min {
                 *(self.board.getm(pos)) = False;
@@ -581,38 +714,26 @@ impl<T : Eq + Hash + Enumerable + Clone, S : Board<T>> Puzzle<T, S> {
     /// @param max: max number of iterations to try
     /// @param slow: whether to do only one removal per call. Is not for efficiency
     fn find_odd_loops(&self, max: Option<usize>, slow : bool) -> (usize, Vec<Vec<T>>) {
-        let m = match max {
-            None => 20,
-            Some(e) => e,
-        };
+        let m = max.unwrap_or(20);
 
         let mut min = m + 1;
         let mut to_rem = vec![];
 
         let wg = self.graph();
         let sg = self.graph_strong();
-        let mut wsum = 0;
-        for j in wg.values() {
-            wsum += j.conn.len();
-        }
-        let mut ssum = 0;
-        for j in sg.values() {
-            ssum += j.conn.len();
-        }
-        eprintln!("ln:{},{}", ssum / 2, wsum / 2);
-        let mut succ = false;
-        for (spos, s) in &wg {
+        for spos in wg.keys() {
             let mut visited = HashMap::with_hasher(self.hasher.clone());
             let mut to_visit = HashMap::with_hasher(self.hasher.clone());
             to_visit.insert(spos.clone(), None);
             let mut need_strong = false;
             let mut min_i = min;
             let mut ends = (None,None);
-            'findloop: for i in 0..min {
-                let (graph, check_graph, csum) = if need_strong {
-                    (&sg, &wg, wsum)
+            let limit = min;
+            'findloop: for i in 0..limit {
+                let (graph, check_graph) = if need_strong {
+                    (&sg, &wg)
                 } else {
-                    (&wg, &sg, ssum)
+                    (&wg, &sg)
                 };
                 need_strong = !need_strong;
                 let mut new_visit = HashMap::with_hasher(self.hasher.clone());
@@ -636,19 +757,19 @@ impl<T : Eq + Hash + Enumerable + Clone, S : Board<T>> Puzzle<T, S> {
                 }
                 to_visit.drain();
 
-                /// Checks to see if it can finish a loop
-                let s2 : HashSet<T> = HashSet::from_iter(new_visit.iter().map(|(x, y)| x.to_owned()));
+                // Checks to see if it can finish a loop
+                let s2 : HashSet<T> = HashSet::from_iter(new_visit.keys().cloned());
                 for v in &new_visit {
                     let s1 = HashSet::<T>::from_iter(
                         check_graph
-                            .get(&v.0)
+                            .get(v.0)
                             .unwrap()
                             .conn
                             .iter()
                             .map(|x| x.to_owned()),
                     );
                     let inter: HashSet<_> = s1.intersection(&s2).collect();
-                    if inter.len() > 0 {
+                    if !inter.is_empty() {
                         //eprintln!("From {}:({},{},{}), {}", i, v.0,v.1,v.2, csum/2);
                         /*
                         for i in inter {
@@ -662,13 +783,8 @@ impl<T : Eq + Hash + Enumerable + Clone, S : Board<T>> Puzzle<T, S> {
                         //let (x, y, z) = spos;
                         //*(self.board.getm(*x,*y,*z)) = False;
                         //to_rem.push((i, (*x, *y, *z)));
-                        let mut w = None;
-                        for i in inter {
-                            w = Some(i.clone());
-                            break;
-                        }
+                        let w = inter.into_iter().next().cloned();
                         min_i = i;
-                        succ = true;
                         ends = (Some(v.0.to_owned()), w);
                         visited.extend(new_visit);
                         break 'findloop;
@@ -705,7 +821,6 @@ impl<T : Eq + Hash + Enumerable + Clone, S : Board<T>> Puzzle<T, S> {
                 to_rem.push((min_i,ret));
             }
         }
-        eprintln!("{}", min);
         let mut ret = vec![];
         for (i, v) in to_rem {
             if i <= min {
@@ -719,52 +834,75 @@ impl<T : Eq + Hash + Enumerable + Clone, S : Board<T>> Puzzle<T, S> {
     }
 }
 
-impl<const SIZE: usize>  Puzzle<Tuple3D<SIZE>, SdkBoard<SIZE>> {
-    pub(crate) fn init(size: usize) -> Self
+impl Puzzle<Tuple3D, SdkBoard> {
+    pub(crate) fn init(board: SdkBoard) -> Self
     {
-        let s = Self {
-            board: SdkBoard {
-                data: vec![Poss; size * size * size],
-            },
+        Self {
+            board,
             constraints: vec![],
             hasher: RandomState::new(),
-        };
-        s
+        }
     }
 }
 
-impl<const SIZE: usize>  Display for SdkBoard<SIZE> {
+impl Display for SdkBoard {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for x in 0..SIZE {
-            for y in 0..SIZE {
-                for z in 0..SIZE {
+        let width = self.labels.iter().map(|l| l.chars().count()).max().unwrap_or(1);
+        for x in 0..self.rows {
+            for y in 0..self.cols {
+                let mut label = "?";
+                for z in 0..self.labels.len() {
                     if self.get(&Tuple3D::from((x, y, z))) == True {
-                        write!(f, "{} ", z + 1)?;
+                        label = &self.labels[z];
                         break;
                     }
-                    if z == (SIZE - 1) {
-                        write!(f, "? ")?;
-                    }
                 }
+                write!(f, "{:>width$} ", label, width = width)?;
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
         Ok(())
     }
 }
 
-impl<const SIZE: usize>  Debug for SdkBoard<SIZE> {
+impl Debug for SdkBoard {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for x in 0..SIZE {
-            for y in 0..SIZE {
+        for x in 0..self.rows {
+            for y in 0..self.cols {
                 write!(f, "[")?;
-                for z in 0..SIZE {
+                for z in 0..self.labels.len() {
                     write!(f, "{:?},", *self.get(&Tuple3D::from((x, y, z))))?;
                 }
                 write!(f, "]")?;
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::puzzle_file;
+
+    fn puzzle(src: &str) -> Puzzle<Tuple3D, SdkBoard> {
+        let file = puzzle_file::parse(src).unwrap();
+        let mut game = Puzzle::init(file.board());
+        game.constraints = file.constraints();
+        game
+    }
+
+    #[test]
+    fn limit_of_one_stops_at_the_first_solution() {
+        let solutions = puzzle("size 4\n").count_solutions(1);
+        assert_eq!(solutions.count, SolutionCount::AtLeast(1));
+        assert_eq!(solutions.examples.len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 1")]
+    fn limit_of_zero_is_rejected() {
+        puzzle("size 4\n").count_solutions(0);
+    }
+}
@@ -1,16 +1,70 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
 use std::hash::Hash;
 use crate::board::LogicVal::{False, Poss, True};
-use crate::board::{Board, Puzzle, LogicVal, Enumerable, Tuple3D};
+use crate::layout::Edge;
+use crate::board::{Board, Grid, Tuple3D};
+
+/// A rule the board can no longer satisfy
+#[derive(Debug)]
+pub struct Contradiction<T> {
+    /// Which rule broke, like "row 3"
+    pub rule: String,
+    /// The positions that broke it
+    pub cells: Vec<T>,
+}
+
+impl<T> Contradiction<T> {
+    pub fn new(rule: String, cells: Vec<T>) -> Self {
+        Contradiction { rule, cells }
+    }
+}
+
+/// r<row>c<col>, counting from 1
+pub fn cell_name(x: usize, y: usize) -> String {
+    format!("r{}c{}", x + 1, y + 1)
+}
+
+/// "row 3" or "column 3" for straight regions, otherwise where the region starts
+pub(crate) fn region_name(cells: &[(usize, usize)]) -> String {
+    match cells.first() {
+        Some((x, _)) if cells.len() > 1 && cells.iter().all(|c| c.0 == *x) => format!("row {}", x + 1),
+        Some((_, y)) if cells.len() > 1 && cells.iter().all(|c| c.1 == *y) => format!("column {}", y + 1),
+        Some((x, y)) => format!("region at {}", cell_name(*x, *y)),
+        None => String::from("empty region"),
+    }
+}
+
+/// Structure a rule gives the grid, which the technique solver reasons with directly
+pub enum House {
+    /// No digit repeats in these cells, and when `full` every digit is in them
+    Region { cells: Vec<(usize, usize)>, full: bool },
+    /// Every cell holds exactly one digit
+    EachCell,
+}
 
 /// A constraint can only remove a possibility/pencil mark
-pub(crate) trait Constraint<T : Eq + Hash + Enumerable, S:Board<T>>{
-    /// Remove all illegal pencil marks
-    fn apply(&self, board: &mut S) -> bool;
+pub(crate) trait Constraint<T : Eq + Hash, S:Board<T>>{
+    /// Remove all illegal pencil marks.
+    /// Returns whether anything changed, or what broke if the board can't satisfy the rule
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<T>>;
 
     /// Get all tiles that this rule affects from one tile
     /// Effect of "guessing" True on that tile
     fn affects(&self, board: &S, v: &T)
         -> Vec<T>;
+
+    /// The houses this rule makes. The technique solver handles rules with houses through
+    /// its own reasoning instead of calling `apply`
+    fn houses(&self, _board: &S) -> Vec<House> {
+        vec![]
+    }
+
+    /// Sets of positions at least one of which has to be True. Two positions left in one
+    /// of these make a strong link for chains
+    fn strong_sets(&self, _board: &S) -> Vec<Vec<T>> {
+        vec![]
+    }
 }
 
 pub struct RowUniqueConstraint;
@@ -20,17 +74,18 @@ impl Clone for RowUniqueConstraint {
         RowUniqueConstraint
     }
 
-    fn clone_from(&mut self, source: &Self) {}
+    fn clone_from(&mut self, _source: &Self) {}
 }
 
-impl<S : Board<Tuple3D<SIZE>>, const SIZE:usize> Constraint<Tuple3D<SIZE>, S> for RowUniqueConstraint {
-    fn apply(&self, board: &mut S) -> bool {
+impl<S : Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for RowUniqueConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
         let mut did = false;
-        for v in &Tuple3D::<SIZE>::positions() {
+        for v in &board.positions() {
             if board.get(v) == True {
-                for y_ in 0..SIZE {
+                for y_ in 0..board.cols() {
                     let to_rem = Tuple3D::from((v.pos.0, y_, v.pos.2));
                     *(board.getm(&to_rem)) = match board.get(&to_rem) {
+                        True if *v != to_rem => return Err(Contradiction::new(format!("row {}", v.pos.0 + 1), vec![*v, to_rem])),
                         True => True,
                         False => False,
                         Poss => {
@@ -41,20 +96,20 @@ impl<S : Board<Tuple3D<SIZE>>, const SIZE:usize> Constraint<Tuple3D<SIZE>, S> fo
                 }
             }
         }
-        did
+        Ok(did)
     }
 
     fn affects(
         &self,
         board: &S,
-        v: &Tuple3D<SIZE>
-    ) -> Vec<Tuple3D<SIZE>> {
+        v: &Tuple3D
+    ) -> Vec<Tuple3D> {
         let mut ret = Vec::new();
         let (x,y,z) = v.pos;
         if board.get(v) != Poss {
             return ret;
         }
-        for y_ in 0..SIZE {
+        for y_ in 0..board.cols() {
             if y == y_ {
                 continue;
             }
@@ -65,6 +120,12 @@ impl<S : Board<Tuple3D<SIZE>>, const SIZE:usize> Constraint<Tuple3D<SIZE>, S> fo
         }
         ret
     }
+
+    fn houses(&self, board: &S) -> Vec<House> {
+        (0..board.rows())
+            .map(|x| House::Region { cells: (0..board.cols()).map(|y| (x, y)).collect(), full: false })
+            .collect()
+    }
 }
 
 pub struct RowExistConstraint;
@@ -74,23 +135,27 @@ impl Clone for RowExistConstraint {
         Self
     }
 
-    fn clone_from(&mut self, source: &Self) {}
+    fn clone_from(&mut self, _source: &Self) {}
 }
 
-impl<S: Board<Tuple3D<SIZE>>, const SIZE: usize> Constraint<Tuple3D<SIZE>, S> for RowExistConstraint {
-    fn apply(&self, board: &mut S) -> bool {
+impl<S: Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for RowExistConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
         let mut did = false;
-        for x in 0..SIZE {
-            for z in 0..SIZE {
+        for x in 0..board.rows() {
+            for z in 0..board.digits() {
                 let mut poss_count = 0;
-                for y in 0..SIZE {
+                for y in 0..board.cols() {
                     if board.get(&Tuple3D::from((x,y,z))) != False {
                         poss_count += 1;
                     }
                 }
+                if poss_count == 0 {
+                    let cells = (0..board.cols()).map(|y| Tuple3D::from((x,y,z))).collect();
+                    return Err(Contradiction::new(format!("row {}", x + 1), cells));
+                }
                 if poss_count == 1 {
                     //eprintln!("Row found unique");
-                    for y_ in 0..SIZE {
+                    for y_ in 0..board.cols() {
                         let pos = Tuple3D::from((x,y_,z));
                         *(board.getm(&pos)) = match board.get(&pos) {
                             False => {False}
@@ -101,12 +166,28 @@ impl<S: Board<Tuple3D<SIZE>>, const SIZE: usize> Constraint<Tuple3D<SIZE>, S> fo
                 }
             }
         }
-        did
+        Ok(did)
     }
 
-    fn affects(&self, board: &S, v: &Tuple3D<SIZE>) -> Vec<Tuple3D<SIZE>> {
+    fn affects(&self, _board: &S, _v: &Tuple3D) -> Vec<Tuple3D> {
         vec![]
     }
+
+    fn houses(&self, board: &S) -> Vec<House> {
+        (0..board.rows())
+            .map(|x| House::Region { cells: (0..board.cols()).map(|y| (x, y)).collect(), full: true })
+            .collect()
+    }
+
+    fn strong_sets(&self, board: &S) -> Vec<Vec<Tuple3D>> {
+        let mut ret = vec![];
+        for x in 0..board.rows() {
+            for z in 0..board.digits() {
+                ret.push((0..board.cols()).map(|y| Tuple3D::from((x, y, z))).collect());
+            }
+        }
+        ret
+    }
 }
 
 pub struct ColUniqueConstraint;
@@ -116,19 +197,20 @@ impl Clone for ColUniqueConstraint {
         Self
     }
 
-    fn clone_from(&mut self, source: &Self) where Self: {
+    fn clone_from(&mut self, _source: &Self) where Self: {
     }
 }
 
-impl<S : Board<Tuple3D<SIZE>>, const SIZE:usize> Constraint<Tuple3D<SIZE>, S> for ColUniqueConstraint {
-    fn apply(&self, board: &mut S) -> bool {
+impl<S : Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for ColUniqueConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
         let mut did = false;
-        for v in &Tuple3D::positions() {
-            let (x,y,z) = v.pos;
+        for v in &board.positions() {
+            let (_,y,z) = v.pos;
             if board.get(v) == True {
-                for x_ in 0..SIZE {
+                for x_ in 0..board.rows() {
                     let to_rem = Tuple3D::from((x_, y, z));
                     *(board.getm(&to_rem)) = match board.get(&to_rem) {
+                        True if *v != to_rem => return Err(Contradiction::new(format!("column {}", y + 1), vec![*v, to_rem])),
                         True => True,
                         False => False,
                         Poss => {
@@ -139,20 +221,20 @@ impl<S : Board<Tuple3D<SIZE>>, const SIZE:usize> Constraint<Tuple3D<SIZE>, S> fo
                 }
             }
         }
-        did
+        Ok(did)
     }
 
     fn affects(
         &self,
         board: &S,
-        v: &Tuple3D<SIZE>
-    ) -> Vec<Tuple3D<SIZE>> {
+        v: &Tuple3D
+    ) -> Vec<Tuple3D> {
         let (x,y,z) = v.pos;
         let mut ret = Vec::new();
         if board.get(v) != Poss {
             return ret;
         }
-        for x_ in 0..SIZE {
+        for x_ in 0..board.rows() {
             if x == x_ {
                 continue;
             }
@@ -163,6 +245,12 @@ impl<S : Board<Tuple3D<SIZE>>, const SIZE:usize> Constraint<Tuple3D<SIZE>, S> fo
         }
         ret
     }
+
+    fn houses(&self, board: &S) -> Vec<House> {
+        (0..board.cols())
+            .map(|y| House::Region { cells: (0..board.rows()).map(|x| (x, y)).collect(), full: false })
+            .collect()
+    }
 }
 
 pub struct ColExistConstraint;
@@ -172,22 +260,26 @@ impl Clone for ColExistConstraint {
         Self
     }
 
-    fn clone_from(&mut self, source: &Self) {}
+    fn clone_from(&mut self, _source: &Self) {}
 }
 
-impl<S: Board<Tuple3D<SIZE>>, const SIZE: usize> Constraint<Tuple3D<SIZE>, S> for ColExistConstraint {
-    fn apply(&self, board: &mut S) -> bool {
+impl<S: Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for ColExistConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
         let mut did = false;
-        for y in 0..SIZE {
-            for z in 0..SIZE {
+        for y in 0..board.cols() {
+            for z in 0..board.digits() {
                 let mut poss_count = 0;
-                for x in 0..SIZE {
+                for x in 0..board.rows() {
                     if board.get(&Tuple3D::from((x,y,z))) != False {
                         poss_count += 1;
                     }
                 }
+                if poss_count == 0 {
+                    let cells = (0..board.rows()).map(|x| Tuple3D::from((x,y,z))).collect();
+                    return Err(Contradiction::new(format!("column {}", y + 1), cells));
+                }
                 if poss_count == 1 {
-                    for x_ in 0..SIZE {
+                    for x_ in 0..board.rows() {
                         let pos = Tuple3D::from((x_,y,z));
                         *(board.getm(&pos)) = match board.get(&pos) {
                             False => {False}
@@ -198,12 +290,28 @@ impl<S: Board<Tuple3D<SIZE>>, const SIZE: usize> Constraint<Tuple3D<SIZE>, S> fo
                 }
             }
         }
-        did
+        Ok(did)
     }
 
-    fn affects(&self, board: &S, v: &Tuple3D<SIZE>) -> Vec<Tuple3D<SIZE>> {
+    fn affects(&self, _board: &S, _v: &Tuple3D) -> Vec<Tuple3D> {
         vec![]
     }
+
+    fn houses(&self, board: &S) -> Vec<House> {
+        (0..board.cols())
+            .map(|y| House::Region { cells: (0..board.rows()).map(|x| (x, y)).collect(), full: true })
+            .collect()
+    }
+
+    fn strong_sets(&self, board: &S) -> Vec<Vec<Tuple3D>> {
+        let mut ret = vec![];
+        for y in 0..board.cols() {
+            for z in 0..board.digits() {
+                ret.push((0..board.rows()).map(|x| Tuple3D::from((x, y, z))).collect());
+            }
+        }
+        ret
+    }
 }
 
 pub struct DigitUniqueConstraint;
@@ -213,20 +321,21 @@ impl Clone for DigitUniqueConstraint {
         DigitUniqueConstraint
     }
 
-    fn clone_from(&mut self, source: &Self) where Self: {
+    fn clone_from(&mut self, _source: &Self) where Self: {
 
     }
 }
 
-impl<S : Board<Tuple3D<SIZE>>, const SIZE:usize> Constraint<Tuple3D<SIZE>, S> for DigitUniqueConstraint {
-    fn apply(&self, board: &mut S) -> bool {
+impl<S : Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for DigitUniqueConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
         let mut did = false;
-        for v in Tuple3D::<SIZE>::positions() {
-            let (x,y,z) = v.pos;
+        for v in board.positions() {
+            let (x,y,_) = v.pos;
             if board.get(&v) == True {
-                for z_ in 0..SIZE {
+                for z_ in 0..board.digits() {
                     let ret_pos = Tuple3D::from((x, y, z_));
                     *(board.getm(&ret_pos)) = match board.get(&ret_pos) {
+                        True if v != ret_pos => return Err(Contradiction::new(cell_name(x, y), vec![v, ret_pos])),
                         True => True,
                         False => False,
                         Poss => {
@@ -237,20 +346,20 @@ impl<S : Board<Tuple3D<SIZE>>, const SIZE:usize> Constraint<Tuple3D<SIZE>, S> fo
                 }
             }
         }
-        did
+        Ok(did)
     }
 
     fn affects(
         &self,
         board: &S,
-        v: &Tuple3D<SIZE>
-    ) -> Vec<Tuple3D<SIZE>> {
+        v: &Tuple3D
+    ) -> Vec<Tuple3D> {
         let (x,y,z) = v.pos;
         let mut ret = Vec::new();
         if board.get(v) != Poss {
             return ret;
         }
-        for z_ in 0..SIZE {
+        for z_ in 0..board.digits() {
             if z == z_ {
                 continue;
             }
@@ -261,6 +370,10 @@ impl<S : Board<Tuple3D<SIZE>>, const SIZE:usize> Constraint<Tuple3D<SIZE>, S> fo
         }
         ret
     }
+
+    fn houses(&self, _board: &S) -> Vec<House> {
+        vec![House::EachCell]
+    }
 }
 
 pub struct DigitExistConstraint;
@@ -270,22 +383,26 @@ impl Clone for DigitExistConstraint {
         Self
     }
 
-    fn clone_from(&mut self, source: &Self) {}
+    fn clone_from(&mut self, _source: &Self) {}
 }
 
-impl<S: Board<Tuple3D<SIZE>>, const SIZE: usize> Constraint<Tuple3D<SIZE>, S> for DigitExistConstraint {
-    fn apply(&self, board: &mut S) -> bool {
+impl<S: Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for DigitExistConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
         let mut did = false;
-        for x in 0..SIZE {
-            for y in 0..SIZE {
+        for x in 0..board.rows() {
+            for y in 0..board.cols() {
                 let mut poss_count = 0;
-                for z in 0..SIZE {
+                for z in 0..board.digits() {
                     if board.get(&Tuple3D::from((x,y,z))) != False {
                         poss_count += 1;
                     }
                 }
+                if poss_count == 0 {
+                    let cells = (0..board.digits()).map(|z| Tuple3D::from((x,y,z))).collect();
+                    return Err(Contradiction::new(cell_name(x, y), cells));
+                }
                 if poss_count == 1 {
-                    for z_ in 0..SIZE {
+                    for z_ in 0..board.digits() {
                         let pos = Tuple3D::from((x,y,z_));
                         *(board.getm(&pos)) = match board.get(&pos) {
                             False => {False}
@@ -296,12 +413,26 @@ impl<S: Board<Tuple3D<SIZE>>, const SIZE: usize> Constraint<Tuple3D<SIZE>, S> fo
                 }
             }
         }
-        did
+        Ok(did)
     }
 
-    fn affects(&self, board: &S, v: &Tuple3D<SIZE>) -> Vec<Tuple3D<SIZE>> {
+    fn affects(&self, _board: &S, _v: &Tuple3D) -> Vec<Tuple3D> {
         vec![]
     }
+
+    fn houses(&self, _board: &S) -> Vec<House> {
+        vec![House::EachCell]
+    }
+
+    fn strong_sets(&self, board: &S) -> Vec<Vec<Tuple3D>> {
+        let mut ret = vec![];
+        for x in 0..board.rows() {
+            for y in 0..board.cols() {
+                ret.push((0..board.digits()).map(|z| Tuple3D::from((x, y, z))).collect());
+            }
+        }
+        ret
+    }
 }
 
 pub struct CellConstraint {
@@ -318,14 +449,17 @@ impl Clone for CellConstraint {
     }
 }
 
-impl<S : Board<Tuple3D<SIZE>>, const SIZE:usize> Constraint<Tuple3D<SIZE>, S> for CellConstraint {
-    fn apply(&self, board: &mut S) -> bool {
+impl<S : Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for CellConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
         let mut did = false;
         for (x, y) in &self.cells {
-            for z in 0..SIZE {
-                if board.get(&Tuple3D::from((*x, *y, z))) == True {
+            for z in 0..board.digits() {
+                let v = Tuple3D::from((*x, *y, z));
+                if board.get(&v) == True {
                     for (x_, y_) in &self.cells {
-                        *(board.getm(&Tuple3D::from((*x_, *y_, z)))) = match board.get(&Tuple3D::from((*x_, *y_, z))) {
+                        let to_rem = Tuple3D::from((*x_, *y_, z));
+                        *(board.getm(&to_rem)) = match board.get(&to_rem) {
+                            True if v != to_rem => return Err(Contradiction::new(region_name(&self.cells), vec![v, to_rem])),
                             True => True,
                             False => False,
                             Poss => {
@@ -337,14 +471,14 @@ impl<S : Board<Tuple3D<SIZE>>, const SIZE:usize> Constraint<Tuple3D<SIZE>, S> fo
                 }
             }
         }
-        did
+        Ok(did)
     }
 
     fn affects(
         &self,
         board: &S,
-        v: &Tuple3D<SIZE>
-    ) -> Vec<Tuple3D<SIZE>> {
+        v: &Tuple3D
+    ) -> Vec<Tuple3D> {
         let (x,y,z) = v.pos;
         let mut ret = Vec::new();
         if board.get(v) != Poss || !self.cells.contains(&(x, y)) {
@@ -361,6 +495,68 @@ impl<S : Board<Tuple3D<SIZE>>, const SIZE:usize> Constraint<Tuple3D<SIZE>, S> fo
         }
         ret
     }
+
+    fn houses(&self, _board: &S) -> Vec<House> {
+        vec![House::Region { cells: self.cells.clone(), full: false }]
+    }
+}
+
+/// Every digit appears somewhere in the cells
+pub struct CellExistConstraint {
+    pub(crate) cells: Vec<(usize, usize)>,
+}
+
+impl Clone for CellExistConstraint {
+    fn clone(&self) -> Self {
+        CellExistConstraint{ cells : self.cells.clone()}
+    }
+
+    fn clone_from(&mut self, source: &Self) where Self: {
+        self.cells = source.cells.clone();
+    }
+}
+
+impl<S: Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for CellExistConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
+        let mut did = false;
+        for z in 0..board.digits() {
+            let mut poss_count = 0;
+            for (x, y) in &self.cells {
+                if board.get(&Tuple3D::from((*x,*y,z))) != False {
+                    poss_count += 1;
+                }
+            }
+            if poss_count == 0 {
+                let cells = self.cells.iter().map(|(x, y)| Tuple3D::from((*x,*y,z))).collect();
+                return Err(Contradiction::new(region_name(&self.cells), cells));
+            }
+            if poss_count == 1 {
+                for (x, y) in &self.cells {
+                    let pos = Tuple3D::from((*x,*y,z));
+                    *(board.getm(&pos)) = match board.get(&pos) {
+                        False => {False}
+                        True => {True}
+                        Poss => {did=true; True}
+                    };
+                }
+            }
+        }
+        Ok(did)
+    }
+
+    fn affects(&self, _board: &S, _v: &Tuple3D) -> Vec<Tuple3D> {
+        vec![]
+    }
+
+    fn houses(&self, _board: &S) -> Vec<House> {
+        vec![House::Region { cells: self.cells.clone(), full: true }]
+    }
+
+    fn strong_sets(&self, board: &S) -> Vec<Vec<Tuple3D>> {
+        (0..board.digits())
+            .map(|z| self.cells.iter().map(|(x, y)| Tuple3D::from((*x, *y, z))).collect())
+            .collect()
+    }
 }
 
 pub struct GivenConstraint {
@@ -377,116 +573,1611 @@ impl Clone for GivenConstraint {
     }
 }
 
-impl<S : Board<Tuple3D<SIZE>>, const SIZE:usize> Constraint<Tuple3D<SIZE>, S> for GivenConstraint {
-    fn apply(&self, board: &mut S) -> bool {
+impl<S : Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for GivenConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
         let (x, y, z) = self.pos;
         let ret_pos = Tuple3D::from((x, y, z));
-        let did = board.get(&ret_pos) == Poss;
+        let did = match board.get(&ret_pos) {
+            True => false,
+            Poss => true,
+            False => return Err(Contradiction::new(format!("given {}", cell_name(x, y)), vec![ret_pos])),
+        };
         *(board.getm(&ret_pos)) = True;
-        did
+        Ok(did)
     }
 
     fn affects(
         &self,
         _board: &S,
-        _v: &Tuple3D<SIZE>
-    ) -> Vec<Tuple3D<SIZE>> {
+        _v: &Tuple3D
+    ) -> Vec<Tuple3D> {
         vec![]
     }
 }
 
-pub struct LessThanConstraint {
-    pub(crate) lpos: (usize, usize),
-    pub(crate) hpos: (usize, usize),
+/// The cell holds one of these digits, like a shaded even cell
+pub struct DigitSetConstraint {
+    /// What the marking is called, like "even"
+    pub(crate) name: String,
+    pub(crate) cell: (usize, usize),
+    pub(crate) digits: Vec<usize>,
 }
 
-impl Clone for LessThanConstraint {
+impl Clone for DigitSetConstraint {
     fn clone(&self) -> Self {
-        LessThanConstraint{lpos: self.lpos, hpos: self.hpos}
+        DigitSetConstraint{ name: self.name.clone(), cell: self.cell, digits: self.digits.clone() }
     }
 
     fn clone_from(&mut self, source: &Self) where Self: {
-        self.lpos = source.lpos;
-        self.hpos = source.hpos;
+        self.name = source.name.clone();
+        self.cell = source.cell;
+        self.digits = source.digits.clone();
     }
 }
 
-impl<S : Board<Tuple3D<SIZE>>, const SIZE:usize> Constraint<Tuple3D<SIZE>, S> for LessThanConstraint {
-    fn apply(&self, board: &mut S) -> bool {
-        let (xl, yl) = self.lpos;
-        let (xh, yh) = self.hpos;
+impl<S : Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for DigitSetConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
+        let (x, y) = self.cell;
+        let name = || format!("{} cell {}", self.name, cell_name(x, y));
         let mut did = false;
-        for zl in 0..SIZE {
-            if board.get(&Tuple3D::from((xl, yl, zl))) == Poss {
-                let mut to_rem = true;
-                for zh in (zl + 1)..SIZE {
-                    if board.get(&Tuple3D::from((xh, yh, zh))) != False {
-                        to_rem = false;
-                        break;
-                    }
-                }
-                if to_rem {
+        for z in 0..board.digits() {
+            let pos = Tuple3D::from((x, y, z));
+            if self.digits.contains(&z) {
+                continue;
+            }
+            match board.get(&pos) {
+                True => return Err(Contradiction::new(name(), vec![pos])),
+                Poss => {
+                    *(board.getm(&pos)) = False;
                     did = true;
-                    *(board.getm(&Tuple3D::from((xl, yl, zl)))) = False;
                 }
+                False => {}
             }
-            if board.get(&Tuple3D::from((xh, yh, zl))) == Poss {
-                let mut to_rem = true;
-                for zh in 0..zl {
-                    if board.get(&Tuple3D::from((xl, yl, zh))) != False {
-                        to_rem = false;
-                        break;
-                    }
+        }
+        if self.digits.iter().all(|z| board.get(&Tuple3D::from((x, y, *z))) == False) {
+            let cells = self.digits.iter().map(|z| Tuple3D::from((x, y, *z))).collect();
+            return Err(Contradiction::new(name(), cells));
+        }
+        Ok(did)
+    }
+
+    fn affects(
+        &self,
+        _board: &S,
+        _v: &Tuple3D
+    ) -> Vec<Tuple3D> {
+        vec![]
+    }
+}
+
+/// How the digits in two cells can relate, by the numbers they stand for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Relation {
+    /// The first is less than the second
+    Less,
+    /// They differ by exactly this much, like a white Kropki dot
+    Difference(i64),
+    /// One is this many times the other, like a black Kropki dot
+    Ratio(i64),
+    /// They add up to this, like X and V
+    Sum(i64),
+    /// They differ by this much or more, like neighbours on a whispers line
+    AtLeast(i64),
+    /// They are the same, like mirrored cells on a palindrome
+    Equal,
+}
+
+impl Relation {
+    pub fn holds(&self, a: i64, b: i64) -> bool {
+        match self {
+            Relation::Less => a < b,
+            Relation::Difference(n) => (a - b).abs() == *n,
+            Relation::Ratio(n) => a == b * n || b == a * n,
+            Relation::Sum(n) => a + b == *n,
+            Relation::AtLeast(n) => (a - b).abs() >= *n,
+            Relation::Equal => a == b,
+        }
+    }
+}
+
+impl Display for Relation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Relation::Less => write!(f, "increasing"),
+            Relation::Difference(n) => write!(f, "{} apart", n),
+            Relation::Ratio(n) => write!(f, "in ratio 1:{}", n),
+            Relation::Sum(n) => write!(f, "adding to {}", n),
+            Relation::AtLeast(n) => write!(f, "at least {} apart", n),
+            Relation::Equal => write!(f, "equal"),
+        }
+    }
+}
+
+/// Two cells whose digits satisfy one of the relations, or when `negated` none of them
+pub struct PairConstraint {
+    pub(crate) a: (usize, usize),
+    pub(crate) b: (usize, usize),
+    pub(crate) relations: Vec<Relation>,
+    pub(crate) negated: bool,
+}
+
+impl Clone for PairConstraint {
+    fn clone(&self) -> Self {
+        PairConstraint{a: self.a, b: self.b, relations: self.relations.clone(), negated: self.negated}
+    }
+
+    fn clone_from(&mut self, source: &Self) where Self: {
+        self.a = source.a;
+        self.b = source.b;
+        self.relations = source.relations.clone();
+        self.negated = source.negated;
+    }
+}
+
+impl PairConstraint {
+    /// The first cell is less than the second
+    pub fn less(a: (usize, usize), b: (usize, usize)) -> Self {
+        PairConstraint { a, b, relations: vec![Relation::Less], negated: false }
+    }
+
+    fn name(&self) -> String {
+        let (a, b) = (cell_name(self.a.0, self.a.1), cell_name(self.b.0, self.b.1));
+        let relations: Vec<String> = self.relations.iter().map(|r| r.to_string()).collect();
+        match (&self.relations[..], self.negated) {
+            ([Relation::Less], false) => format!("{} < {}", a, b),
+            (_, false) => format!("{} and {} {}", a, b, relations.join(" or ")),
+            (_, true) => format!("{} and {} not {}", a, b, relations.join(" or ")),
+        }
+    }
+
+    fn allowed(&self, a: i64, b: i64) -> bool {
+        self.relations.iter().any(|r| r.holds(a, b)) != self.negated
+    }
+
+    /// The cell to look from and the other one
+    fn sides(&self, from_a: bool) -> ((usize, usize), (usize, usize)) {
+        if from_a { (self.a, self.b) } else { (self.b, self.a) }
+    }
+}
+
+impl<S : Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for PairConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
+        let (xa, ya) = self.a;
+        let (xb, yb) = self.b;
+        for za in 0..board.digits() {
+            for zb in 0..board.digits() {
+                let (a, b) = (Tuple3D::from((xa, ya, za)), Tuple3D::from((xb, yb, zb)));
+                if board.get(&a) == True && board.get(&b) == True && !self.allowed(board.value(za), board.value(zb)) {
+                    return Err(Contradiction::new(self.name(), vec![a, b]));
+                }
+            }
+        }
+        let mut did = false;
+        for from_a in [true, false] {
+            let ((x, y), (x_, y_)) = self.sides(from_a);
+            for z in 0..board.digits() {
+                let pos = Tuple3D::from((x, y, z));
+                if board.get(&pos) != Poss {
+                    continue;
                 }
-                if to_rem {
+                let supported = (0..board.digits()).any(|z_| {
+                    let (va, vb) = if from_a { (z, z_) } else { (z_, z) };
+                    board.get(&Tuple3D::from((x_, y_, z_))) != False && self.allowed(board.value(va), board.value(vb))
+                });
+                if !supported {
                     did = true;
-                    *(board.getm(&Tuple3D::from((xh, yh, zl)))) = False;
+                    *(board.getm(&pos)) = False;
                 }
             }
         }
-        did
+        Ok(did)
     }
 
     fn affects(
         &self,
         board: &S,
-        v: &Tuple3D<SIZE>
-    ) -> Vec<Tuple3D<SIZE>> {
-        let (xl, yl) = self.lpos;
-        let (xh, yh) = self.hpos;
+        v: &Tuple3D
+    ) -> Vec<Tuple3D> {
         let mut ret = vec![];
-        let (x,y,z) = v.pos;
-        if board.get(v) != Poss || !((x, y) == self.lpos || (x, y) == self.hpos) {
+        let (x, y, z) = v.pos;
+        if board.get(v) != Poss || !((x, y) == self.a || (x, y) == self.b) {
             return ret;
         }
-        if (x, y) == self.lpos {
-            for zh in 0..z {
-                let ret_pos = Tuple3D::from((xh, yh, zh));
-                match board.get(&ret_pos) {
-                    Poss => ret.push(ret_pos),
-                    _ => {}
-                }
-            }
-        } else {
-            for zl in (z + 1)..SIZE {
-                let ret_pos = Tuple3D::from((xl, yl, zl));
-                match board.get(&ret_pos) {
-                    Poss => ret.push(ret_pos),
-                    _ => {}
-                }
+        let from_a = (x, y) == self.a;
+        let (_, (x_, y_)) = self.sides(from_a);
+        for z_ in 0..board.digits() {
+            let ret_pos = Tuple3D::from((x_, y_, z_));
+            let (va, vb) = if from_a { (z, z_) } else { (z_, z) };
+            if board.get(&ret_pos) == Poss && !self.allowed(board.value(va), board.value(vb)) {
+                ret.push(ret_pos);
             }
         }
         ret
     }
 }
 
-pub fn thermo_constraint(cells: Vec<(usize, usize)>) -> Vec<LessThanConstraint> {
+pub fn thermo_constraint(cells: Vec<(usize, usize)>) -> Vec<PairConstraint> {
+    cells.windows(2).map(|w| PairConstraint::less(w[0], w[1])).collect()
+}
+
+/// Neighbours along the line differ by at least `gap`: 5 for German whispers, 4 for Dutch
+pub fn whisper_constraint(cells: Vec<(usize, usize)>, gap: i64) -> Vec<PairConstraint> {
+    cells.windows(2)
+        .map(|w| PairConstraint { a: w[0], b: w[1], relations: vec![Relation::AtLeast(gap)], negated: false })
+        .collect()
+}
+
+/// The line reads the same from either end
+pub fn palindrome_constraint(cells: Vec<(usize, usize)>) -> Vec<PairConstraint> {
+    cells.iter().zip(cells.iter().rev())
+        .take(cells.len() / 2)
+        .map(|(a, b)| PairConstraint { a: *a, b: *b, relations: vec![Relation::Equal], negated: false })
+        .collect()
+}
+
+/// Which digits each cell could still be. A solved cell can only be its digit
+fn candidates<S: Board<Tuple3D> + Grid>(board: &S, cells: &[(usize, usize)]) -> Vec<Vec<bool>> {
+    cells.iter().map(|(x, y)| {
+        let solved = (0..board.digits()).any(|z| board.get(&Tuple3D::from((*x, *y, z))) == True);
+        let wanted = if solved { True } else { Poss };
+        (0..board.digits()).map(|z| board.get(&Tuple3D::from((*x, *y, z))) == wanted).collect()
+    }).collect()
+}
+
+/// Positions that are True in the cells, to point at when a sum can't work
+fn solved_positions<S: Board<Tuple3D> + Grid>(board: &S, cells: &[(usize, usize)]) -> Vec<Tuple3D> {
+    cells.iter()
+        .flat_map(|(x, y)| (0..board.digits()).map(move |z| Tuple3D::from((*x, *y, z))))
+        .filter(|v| board.get(v) == True)
+        .collect()
+}
+
+/// Removes the digits `supports` doesn't back from the cells, or fails when a cell
+/// has none left
+fn prune_by_supports<S: Board<Tuple3D> + Grid>(board: &mut S, cells: &[(usize, usize)], supports: &[Vec<bool>], name: impl FnOnce() -> String) -> Result<bool, Contradiction<Tuple3D>> {
+    let mut did = false;
+    for (i, (x, y)) in cells.iter().enumerate() {
+        if !supports[i].contains(&true) {
+            return Err(Contradiction::new(name(), solved_positions(board, cells)));
+        }
+        for (z, supported) in supports[i].iter().enumerate() {
+            let pos = Tuple3D::from((*x, *y, z));
+            if board.get(&pos) == Poss && !supported {
+                *(board.getm(&pos)) = False;
+                did = true;
+            }
+        }
+    }
+    Ok(did)
+}
+
+/// Candidates in the other cells that lose their support once `v` is placed, with
+/// `supports` working out which digits still fit from each cell's candidates
+fn support_links<S: Board<Tuple3D> + Grid>(board: &S, cells: &[(usize, usize)], v: &Tuple3D, supports: impl Fn(&[Vec<bool>]) -> Vec<Vec<bool>>) -> Vec<Tuple3D> {
+    let (x, y, z) = v.pos;
     let mut ret = vec![];
-    for i in 0..(cells.len() - 1) {
-        ret.push(LessThanConstraint {
-            lpos: cells[i],
-            hpos: cells[i + 1],
-        });
+    let Some(i) = cells.iter().position(|c| *c == (x, y)) else { return ret };
+    if board.get(v) != Poss {
+        return ret;
+    }
+    let mut cands = candidates(board, cells);
+    cands[i] = (0..board.digits()).map(|z_| z_ == z).collect();
+    let supports = supports(&cands);
+    for (j, (x_, y_)) in cells.iter().enumerate() {
+        if j == i {
+            continue;
+        }
+        for (z_, supported) in supports[j].iter().enumerate() {
+            let ret_pos = Tuple3D::from((*x_, *y_, z_));
+            if board.get(&ret_pos) == Poss && !supported {
+                ret.push(ret_pos);
+            }
+        }
     }
     ret
 }
+
+/// For each cell and digit, whether the cells can hold different digits from `cands`
+/// that add up to `sum` with that digit in that cell
+fn distinct_sum_supports(cands: &[Vec<bool>], values: &[i64], sum: i64) -> Vec<Vec<bool>> {
+    let mut ret = vec![vec![false; values.len()]; cands.len()];
+    let mut combo = vec![];
+    distinct_sum_combos(cands, values, sum, 0, &mut combo, &mut ret);
+    ret
+}
+
+/// Tries every set of digits from `next` on that completes `combo` to the sum
+fn distinct_sum_combos(cands: &[Vec<bool>], values: &[i64], sum: i64, next: usize, combo: &mut Vec<usize>, ret: &mut [Vec<bool>]) {
+    let n = cands.len();
+    if combo.len() == n {
+        if combo.iter().map(|z| values[*z]).sum::<i64>() == sum {
+            mark_assignments(cands, combo, ret);
+        }
+        return;
+    }
+    for z in next..values.len() {
+        if values.len() - z < n - combo.len() {
+            break;
+        }
+        combo.push(z);
+        distinct_sum_combos(cands, values, sum, z + 1, combo, ret);
+        combo.pop();
+    }
+}
+
+/// Marks every (cell, digit) used by some way of giving each cell one digit of `combo`.
+/// Cells take digits in order, so a set of used digits says how many cells are filled
+fn mark_assignments(cands: &[Vec<bool>], combo: &[usize], ret: &mut [Vec<bool>]) {
+    let n = combo.len();
+    let full = (1usize << n) - 1;
+    let fits = |cell: usize, j: usize| cands[cell][combo[j]];
+    // Whether the first cells can take exactly these digits
+    let mut start = vec![false; full + 1];
+    start[0] = true;
+    for mask in 0..full {
+        let cell = mask.count_ones() as usize;
+        if start[mask] {
+            for j in (0..n).filter(|j| mask & (1 << j) == 0 && fits(cell, *j)) {
+                start[mask | (1 << j)] = true;
+            }
+        }
+    }
+    // Whether the remaining cells can take the digits not in the set
+    let mut end = vec![false; full + 1];
+    end[full] = true;
+    for mask in (0..full).rev() {
+        let cell = mask.count_ones() as usize;
+        end[mask] = (0..n).any(|j| mask & (1 << j) == 0 && fits(cell, j) && end[mask | (1 << j)]);
+    }
+    for mask in (0..full).filter(|m| start[*m]) {
+        let cell = mask.count_ones() as usize;
+        for j in (0..n).filter(|j| mask & (1 << j) == 0 && fits(cell, *j) && end[mask | (1 << j)]) {
+            ret[cell][combo[j]] = true;
+        }
+    }
+}
+
+/// The cells add up to `sum` and no digit repeats in them
+pub struct KillerCageConstraint {
+    pub(crate) cells: Vec<(usize, usize)>,
+    pub(crate) sum: i64,
+}
+
+impl Clone for KillerCageConstraint {
+    fn clone(&self) -> Self {
+        KillerCageConstraint{ cells: self.cells.clone(), sum: self.sum }
+    }
+
+    fn clone_from(&mut self, source: &Self) where Self: {
+        self.cells = source.cells.clone();
+        self.sum = source.sum;
+    }
+}
+
+impl KillerCageConstraint {
+    fn name(&self) -> String {
+        match self.cells.first() {
+            Some((x, y)) => format!("cage of {} at {}", self.sum, cell_name(*x, *y)),
+            None => format!("empty cage of {}", self.sum),
+        }
+    }
+
+    fn supports<S: Board<Tuple3D> + Grid>(&self, board: &S, cands: &[Vec<bool>]) -> Vec<Vec<bool>> {
+        let values: Vec<i64> = (0..board.digits()).map(|z| board.value(z)).collect();
+        distinct_sum_supports(cands, &values, self.sum)
+    }
+}
+
+impl<S : Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for KillerCageConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
+        let supports = self.supports(board, &candidates(board, &self.cells));
+        prune_by_supports(board, &self.cells, &supports, || self.name())
+    }
+
+    fn affects(
+        &self,
+        board: &S,
+        v: &Tuple3D
+    ) -> Vec<Tuple3D> {
+        support_links(board, &self.cells, v, |cands| self.supports(board, cands))
+    }
+}
+
+/// The digits in the circle, read as one number when it is a pill of several cells,
+/// equal the sum of the digits along the arrow
+pub struct ArrowConstraint {
+    pub(crate) circle: Vec<(usize, usize)>,
+    pub(crate) arrow: Vec<(usize, usize)>,
+}
+
+impl Clone for ArrowConstraint {
+    fn clone(&self) -> Self {
+        ArrowConstraint{ circle: self.circle.clone(), arrow: self.arrow.clone() }
+    }
+
+    fn clone_from(&mut self, source: &Self) where Self: {
+        self.circle = source.circle.clone();
+        self.arrow = source.arrow.clone();
+    }
+}
+
+impl ArrowConstraint {
+    fn name(&self) -> String {
+        match self.circle.first() {
+            Some((x, y)) => format!("arrow from {}", cell_name(*x, *y)),
+            None => String::from("arrow without a circle"),
+        }
+    }
+
+    /// The circle's cells then the arrow's
+    fn cells(&self) -> Vec<(usize, usize)> {
+        self.circle.iter().chain(&self.arrow).copied().collect()
+    }
+
+    /// What each cell's digit counts for: its place value in the circle, or 1 on the arrow
+    fn weights(&self) -> Vec<i64> {
+        let places = (0..self.circle.len() as u32).rev().map(|p| 10i64.pow(p));
+        places.chain(self.arrow.iter().map(|_| 1)).collect()
+    }
+
+    /// (cell, digit) candidates that leave the circle and arrow no total in common,
+    /// or None if nothing works at all. Totals only go as far as min and max
+    fn impossible<S: Board<Tuple3D> + Grid>(&self, board: &S, cands: &[Vec<bool>]) -> Option<Vec<(usize, usize)>> {
+        let values: Vec<i64> = (0..board.digits()).map(|z| board.value(z)).collect();
+        let weights = self.weights();
+        let mut lows = vec![];
+        let mut highs = vec![];
+        for (i, c) in cands.iter().enumerate() {
+            let vals: Vec<i64> = (0..values.len()).filter(|z| c[*z]).map(|z| values[z] * weights[i]).collect();
+            lows.push(*vals.iter().min()?);
+            highs.push(*vals.iter().max()?);
+        }
+        let split = self.circle.len();
+        let range = |from: usize, to: usize| (lows[from..to].iter().sum::<i64>(), highs[from..to].iter().sum::<i64>());
+        let circle = range(0, split);
+        let arrow = range(split, cands.len());
+        if circle.0.max(arrow.0) > circle.1.min(arrow.1) {
+            return None;
+        }
+        let mut ret = vec![];
+        for (i, c) in cands.iter().enumerate() {
+            let ((lo, hi), other) = if i < split { (circle, arrow) } else { (arrow, circle) };
+            for z in (0..values.len()).filter(|z| c[*z]) {
+                let v = values[z] * weights[i];
+                if hi - highs[i] + v < other.0 || lo - lows[i] + v > other.1 {
+                    ret.push((i, z));
+                }
+            }
+        }
+        Some(ret)
+    }
+}
+
+impl<S : Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for ArrowConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
+        let cells = self.cells();
+        let Some(impossible) = self.impossible(board, &candidates(board, &cells)) else {
+            return Err(Contradiction::new(self.name(), solved_positions(board, &cells)));
+        };
+        let mut did = false;
+        for (i, z) in impossible {
+            let (x, y) = cells[i];
+            let pos = Tuple3D::from((x, y, z));
+            match board.get(&pos) {
+                True => return Err(Contradiction::new(self.name(), solved_positions(board, &cells))),
+                Poss => {
+                    *(board.getm(&pos)) = False;
+                    did = true;
+                }
+                False => {}
+            }
+        }
+        Ok(did)
+    }
+
+    fn affects(
+        &self,
+        board: &S,
+        v: &Tuple3D
+    ) -> Vec<Tuple3D> {
+        let (x, y, z) = v.pos;
+        let cells = self.cells();
+        let Some(i) = cells.iter().position(|c| *c == (x, y)) else { return vec![] };
+        if board.get(v) != Poss {
+            return vec![];
+        }
+        let mut cands = candidates(board, &cells);
+        cands[i] = (0..board.digits()).map(|z_| z_ == z).collect();
+        self.impossible(board, &cands).unwrap_or_default().into_iter()
+            .filter(|(j, _)| *j != i)
+            .map(|(j, z_)| Tuple3D::from((cells[j].0, cells[j].1, z_)))
+            .filter(|pos| board.get(pos) == Poss)
+            .collect()
+    }
+}
+
+/// The cells hold a run of consecutive digits, in any order and without repeats
+pub struct RenbanConstraint {
+    pub(crate) cells: Vec<(usize, usize)>,
+}
+
+impl Clone for RenbanConstraint {
+    fn clone(&self) -> Self {
+        RenbanConstraint{ cells: self.cells.clone() }
+    }
+
+    fn clone_from(&mut self, source: &Self) where Self: {
+        self.cells = source.cells.clone();
+    }
+}
+
+impl RenbanConstraint {
+    fn name(&self) -> String {
+        match self.cells.first() {
+            Some((x, y)) => format!("renban from {}", cell_name(*x, *y)),
+            None => String::from("empty renban"),
+        }
+    }
+
+    /// For each cell and digit, whether some run of digits as long as the line
+    /// fits the cells with that digit in that cell
+    fn supports<S: Board<Tuple3D> + Grid>(&self, board: &S, cands: &[Vec<bool>]) -> Vec<Vec<bool>> {
+        let mut ret = vec![vec![false; board.digits()]; self.cells.len()];
+        for start in 0..board.digits() {
+            let low = board.value(start);
+            let window: Option<Vec<usize>> = (0..self.cells.len() as i64)
+                .map(|i| (0..board.digits()).find(|z| board.value(*z) == low + i))
+                .collect();
+            if let Some(window) = window {
+                mark_assignments(cands, &window, &mut ret);
+            }
+        }
+        ret
+    }
+}
+
+impl<S : Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for RenbanConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
+        let supports = self.supports(board, &candidates(board, &self.cells));
+        prune_by_supports(board, &self.cells, &supports, || self.name())
+    }
+
+    fn affects(
+        &self,
+        board: &S,
+        v: &Tuple3D
+    ) -> Vec<Tuple3D> {
+        support_links(board, &self.cells, v, |cands| self.supports(board, cands))
+    }
+}
+
+/// The digits on the line are strictly between the digits in the circles at its ends
+pub struct BetweenConstraint {
+    pub(crate) ends: ((usize, usize), (usize, usize)),
+    pub(crate) cells: Vec<(usize, usize)>,
+}
+
+impl Clone for BetweenConstraint {
+    fn clone(&self) -> Self {
+        BetweenConstraint{ ends: self.ends, cells: self.cells.clone() }
+    }
+
+    fn clone_from(&mut self, source: &Self) where Self: {
+        self.ends = source.ends;
+        self.cells = source.cells.clone();
+    }
+}
+
+impl BetweenConstraint {
+    fn name(&self) -> String {
+        let ((xa, ya), (xb, yb)) = self.ends;
+        format!("between line from {} to {}", cell_name(xa, ya), cell_name(xb, yb))
+    }
+
+    /// Both ends then the line
+    fn all_cells(&self) -> Vec<(usize, usize)> {
+        [self.ends.0, self.ends.1].into_iter().chain(self.cells.iter().copied()).collect()
+    }
+
+    /// For each cell and digit, whether some pair of ends leaves every cell of the
+    /// line a digit between them, with that digit in that cell
+    fn supports<S: Board<Tuple3D> + Grid>(&self, board: &S, cands: &[Vec<bool>]) -> Vec<Vec<bool>> {
+        let digits = board.digits();
+        let mut ret = vec![vec![false; digits]; cands.len()];
+        for za in (0..digits).filter(|z| cands[0][*z]) {
+            for zb in (0..digits).filter(|z| cands[1][*z]) {
+                let (lo, hi) = (board.value(za).min(board.value(zb)), board.value(za).max(board.value(zb)));
+                let between = |z: usize| lo < board.value(z) && board.value(z) < hi;
+                if !cands[2..].iter().all(|c| (0..digits).any(|z| c[z] && between(z))) {
+                    continue;
+                }
+                ret[0][za] = true;
+                ret[1][zb] = true;
+                for (i, c) in cands.iter().enumerate().skip(2) {
+                    for z in (0..digits).filter(|z| c[*z] && between(*z)) {
+                        ret[i][z] = true;
+                    }
+                }
+            }
+        }
+        ret
+    }
+}
+
+impl<S : Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for BetweenConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
+        let cells = self.all_cells();
+        let supports = self.supports(board, &candidates(board, &cells));
+        prune_by_supports(board, &cells, &supports, || self.name())
+    }
+
+    fn affects(
+        &self,
+        board: &S,
+        v: &Tuple3D
+    ) -> Vec<Tuple3D> {
+        support_links(board, &self.all_cells(), v, |cands| self.supports(board, cands))
+    }
+}
+
+/// Every total the cells can add up to, when digits may repeat
+fn reachable_sums(cands: &[Vec<bool>], values: &[i64]) -> BTreeSet<i64> {
+    let mut sums = BTreeSet::from([0]);
+    for c in cands {
+        sums = sums.iter()
+            .flat_map(|s| (0..values.len()).filter(|z| c[*z]).map(move |z| s + values[z]))
+            .collect();
+    }
+    sums
+}
+
+/// For each cell and digit, whether the cells can add up to `sum` with that digit in
+/// that cell, when digits may repeat
+fn repeat_sum_supports(cands: &[Vec<bool>], values: &[i64], sum: i64) -> Vec<Vec<bool>> {
+    let mut ret = vec![vec![false; values.len()]; cands.len()];
+    for (i, c) in cands.iter().enumerate() {
+        let before = reachable_sums(&cands[..i], values);
+        let after = reachable_sums(&cands[(i + 1)..], values);
+        for z in (0..values.len()).filter(|z| c[*z]) {
+            ret[i][z] = before.iter().any(|b| after.contains(&(sum - b - values[z])));
+        }
+    }
+    ret
+}
+
+/// A line split where it crosses from one region into the next, with every
+/// segment adding up to the same total
+pub struct RegionSumConstraint {
+    pub(crate) segments: Vec<Vec<(usize, usize)>>,
+}
+
+impl Clone for RegionSumConstraint {
+    fn clone(&self) -> Self {
+        RegionSumConstraint{ segments: self.segments.clone() }
+    }
+
+    fn clone_from(&mut self, source: &Self) where Self: {
+        self.segments = source.segments.clone();
+    }
+}
+
+impl RegionSumConstraint {
+    fn name(&self) -> String {
+        match self.segments.first().and_then(|s| s.first()) {
+            Some((x, y)) => format!("region sum line from {}", cell_name(*x, *y)),
+            None => String::from("empty region sum line"),
+        }
+    }
+
+    fn cells(&self) -> Vec<(usize, usize)> {
+        self.segments.concat()
+    }
+
+    /// For each cell of the line and digit, whether its segment can still reach a total
+    /// every other segment can reach, with that digit in that cell
+    fn supports<S: Board<Tuple3D> + Grid>(&self, board: &S, cands: &[Vec<bool>]) -> Vec<Vec<bool>> {
+        let values: Vec<i64> = (0..board.digits()).map(|z| board.value(z)).collect();
+        let mut bounds = vec![];
+        let mut start = 0;
+        for seg in &self.segments {
+            bounds.push(start..start + seg.len());
+            start += seg.len();
+        }
+        let mut totals: Option<BTreeSet<i64>> = None;
+        for b in &bounds {
+            let sums = reachable_sums(&cands[b.clone()], &values);
+            totals = Some(match totals {
+                Some(t) => t.intersection(&sums).copied().collect(),
+                None => sums,
+            });
+        }
+        let totals = totals.unwrap_or_default();
+        let mut ret = vec![vec![false; values.len()]; cands.len()];
+        for b in bounds {
+            for i in b.clone() {
+                let others: Vec<Vec<bool>> = b.clone().filter(|j| *j != i).map(|j| cands[j].clone()).collect();
+                let rest = reachable_sums(&others, &values);
+                for z in (0..values.len()).filter(|z| cands[i][*z]) {
+                    ret[i][z] = totals.iter().any(|t| rest.contains(&(t - values[z])));
+                }
+            }
+        }
+        ret
+    }
+}
+
+impl<S : Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for RegionSumConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
+        let cells = self.cells();
+        let supports = self.supports(board, &candidates(board, &cells));
+        prune_by_supports(board, &cells, &supports, || self.name())
+    }
+
+    fn affects(
+        &self,
+        board: &S,
+        v: &Tuple3D
+    ) -> Vec<Tuple3D> {
+        support_links(board, &self.cells(), v, |cands| self.supports(board, cands))
+    }
+}
+
+/// The digits between the smallest and largest digit of a row or column add up to the clue
+pub struct SandwichConstraint {
+    pub(crate) edge: Edge,
+    pub(crate) sum: i64,
+}
+
+impl Clone for SandwichConstraint {
+    fn clone(&self) -> Self {
+        SandwichConstraint{ edge: self.edge, sum: self.sum }
+    }
+
+    fn clone_from(&mut self, source: &Self) where Self: {
+        self.edge = source.edge;
+        self.sum = source.sum;
+    }
+}
+
+impl SandwichConstraint {
+    fn name(&self) -> String {
+        format!("sandwich of {} {}", self.sum, self.edge)
+    }
+
+    /// For each cell and digit, whether the crusts can go somewhere with that digit in
+    /// that cell and the filling adding up to the clue
+    fn supports<S: Board<Tuple3D> + Grid>(&self, board: &S, cands: &[Vec<bool>]) -> Vec<Vec<bool>> {
+        let digits = board.digits();
+        let values: Vec<i64> = (0..digits).map(|z| board.value(z)).collect();
+        let lo = (0..digits).min_by_key(|z| values[*z]).unwrap_or(0);
+        let hi = (0..digits).max_by_key(|z| values[*z]).unwrap_or(0);
+        // Cells away from the crusts can't be a crust again
+        let filling: Vec<Vec<bool>> = cands.iter()
+            .map(|c| (0..digits).map(|z| c[z] && z != lo && z != hi).collect())
+            .collect();
+        let mut ret = vec![vec![false; digits]; cands.len()];
+        for i in 0..cands.len() {
+            for j in (i + 1)..cands.len() {
+                for (a, b) in [(lo, hi), (hi, lo)] {
+                    if !cands[i][a] || !cands[j][b] {
+                        continue;
+                    }
+                    let outside = || (0..cands.len()).filter(|k| *k < i || *k > j);
+                    if outside().any(|k| !filling[k].contains(&true)) {
+                        continue;
+                    }
+                    let middle = distinct_sum_supports(&filling[(i + 1)..j], &values, self.sum);
+                    let fits = if j == i + 1 { self.sum == 0 } else { middle[0].contains(&true) };
+                    if !fits {
+                        continue;
+                    }
+                    ret[i][a] = true;
+                    ret[j][b] = true;
+                    for (k, m) in middle.iter().enumerate() {
+                        for z in (0..digits).filter(|z| m[*z]) {
+                            ret[i + 1 + k][z] = true;
+                        }
+                    }
+                    for k in outside() {
+                        for z in (0..digits).filter(|z| filling[k][*z]) {
+                            ret[k][z] = true;
+                        }
+                    }
+                }
+            }
+        }
+        ret
+    }
+}
+
+impl<S : Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for SandwichConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
+        let cells = self.edge.cells(board.rows(), board.cols());
+        let supports = self.supports(board, &candidates(board, &cells));
+        prune_by_supports(board, &cells, &supports, || self.name())
+    }
+
+    fn affects(
+        &self,
+        board: &S,
+        v: &Tuple3D
+    ) -> Vec<Tuple3D> {
+        support_links(board, &self.edge.cells(board.rows(), board.cols()), v, |cands| self.supports(board, cands))
+    }
+}
+
+/// A diagonal from a clue outside the grid adds up to the clue. Digits may repeat
+pub struct LittleKillerConstraint {
+    pub(crate) cells: Vec<(usize, usize)>,
+    pub(crate) sum: i64,
+}
+
+impl Clone for LittleKillerConstraint {
+    fn clone(&self) -> Self {
+        LittleKillerConstraint{ cells: self.cells.clone(), sum: self.sum }
+    }
+
+    fn clone_from(&mut self, source: &Self) where Self: {
+        self.cells = source.cells.clone();
+        self.sum = source.sum;
+    }
+}
+
+impl LittleKillerConstraint {
+    fn name(&self) -> String {
+        match self.cells.first() {
+            Some((x, y)) => format!("little killer of {} from {}", self.sum, cell_name(*x, *y)),
+            None => format!("little killer of {}", self.sum),
+        }
+    }
+
+    fn supports<S: Board<Tuple3D> + Grid>(&self, board: &S, cands: &[Vec<bool>]) -> Vec<Vec<bool>> {
+        let values: Vec<i64> = (0..board.digits()).map(|z| board.value(z)).collect();
+        repeat_sum_supports(cands, &values, self.sum)
+    }
+}
+
+impl<S : Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for LittleKillerConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
+        let supports = self.supports(board, &candidates(board, &self.cells));
+        prune_by_supports(board, &self.cells, &supports, || self.name())
+    }
+
+    fn affects(
+        &self,
+        board: &S,
+        v: &Tuple3D
+    ) -> Vec<Tuple3D> {
+        support_links(board, &self.cells, v, |cands| self.supports(board, cands))
+    }
+}
+
+/// The first X digits of a row or column add up to the clue, where X is the first digit
+pub struct XSumConstraint {
+    pub(crate) edge: Edge,
+    pub(crate) sum: i64,
+}
+
+impl Clone for XSumConstraint {
+    fn clone(&self) -> Self {
+        XSumConstraint{ edge: self.edge, sum: self.sum }
+    }
+
+    fn clone_from(&mut self, source: &Self) where Self: {
+        self.edge = source.edge;
+        self.sum = source.sum;
+    }
+}
+
+impl XSumConstraint {
+    fn name(&self) -> String {
+        format!("x-sum of {} {}", self.sum, self.edge)
+    }
+
+    /// For each cell and digit, whether some first digit counts off cells that can add
+    /// up to the clue with that digit in that cell
+    fn supports<S: Board<Tuple3D> + Grid>(&self, board: &S, cands: &[Vec<bool>]) -> Vec<Vec<bool>> {
+        let digits = board.digits();
+        let values: Vec<i64> = (0..digits).map(|z| board.value(z)).collect();
+        let mut ret = vec![vec![false; digits]; cands.len()];
+        let Some(first) = cands.first() else { return ret };
+        for z in (0..digits).filter(|z| first[*z]) {
+            let count = values[z];
+            if count < 1 || count as usize > cands.len() {
+                continue;
+            }
+            let count = count as usize;
+            let mut counted = cands[..count].to_vec();
+            counted[0] = (0..digits).map(|z_| z_ == z).collect();
+            let sup = distinct_sum_supports(&counted, &values, self.sum);
+            if !sup[0][z] {
+                continue;
+            }
+            for (i, s) in sup.iter().enumerate() {
+                for z_ in (0..digits).filter(|z_| s[*z_]) {
+                    ret[i][z_] = true;
+                }
+            }
+            for i in count..cands.len() {
+                for z_ in (0..digits).filter(|z_| cands[i][*z_]) {
+                    ret[i][z_] = true;
+                }
+            }
+        }
+        ret
+    }
+}
+
+impl<S : Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for XSumConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
+        let cells = self.edge.cells(board.rows(), board.cols());
+        let supports = self.supports(board, &candidates(board, &cells));
+        prune_by_supports(board, &cells, &supports, || self.name())
+    }
+
+    fn affects(
+        &self,
+        board: &S,
+        v: &Tuple3D
+    ) -> Vec<Tuple3D> {
+        support_links(board, &self.edge.cells(board.rows(), board.cols()), v, |cands| self.supports(board, cands))
+    }
+}
+
+/// Reading a row or column from a clue outside the grid, taller digits hide every
+/// shorter one behind them, and the clue counts how many can be seen
+pub struct SkyscraperConstraint {
+    pub(crate) edge: Edge,
+    pub(crate) count: usize,
+}
+
+impl Clone for SkyscraperConstraint {
+    fn clone(&self) -> Self {
+        SkyscraperConstraint{ edge: self.edge, count: self.count }
+    }
+
+    fn clone_from(&mut self, source: &Self) where Self: {
+        self.edge = source.edge;
+        self.count = source.count;
+    }
+}
+
+/// Where a skyscraper line is up to: heights used so far, the tallest one, and how many are seen.
+/// Heights count from 0 for the smallest digit
+type SkyscraperState = (usize, Option<usize>, usize);
+
+impl SkyscraperConstraint {
+    fn name(&self) -> String {
+        format!("skyscraper of {} {}", self.count, self.edge)
+    }
+
+    /// The usual rules for a line holding every digit: the cell `i` from the clue can't be
+    /// one of the `count - 1 - i` tallest, since too few taller ones would be left in front
+    fn edge_rule(&self, digits: usize, cells: usize, i: usize, h: usize) -> bool {
+        cells != digits || h + self.count <= digits + i
+    }
+
+    /// Whether the line can be finished from cell `i` on and show the clue
+    fn finishes(&self, cands: &[Vec<bool>], i: usize, state: SkyscraperState, memo: &mut HashMap<SkyscraperState, bool>) -> bool {
+        let (used, _, seen) = state;
+        if seen > self.count {
+            return false;
+        }
+        if i == cands.len() {
+            return seen == self.count;
+        }
+        if let Some(done) = memo.get(&state) {
+            return *done;
+        }
+        let ret = (0..cands[i].len())
+            .filter(|h| cands[i][*h] && used & (1 << h) == 0)
+            .any(|h| self.finishes(cands, i + 1, Self::place(state, h), memo));
+        memo.insert(state, ret);
+        ret
+    }
+
+    fn place((used, top, seen): SkyscraperState, h: usize) -> SkyscraperState {
+        match top {
+            Some(t) if t > h => (used | (1 << h), top, seen),
+            _ => (used | (1 << h), Some(h), seen + 1),
+        }
+    }
+
+    /// Marks every height used by some order of the line's heights that shows the clue
+    fn mark(&self, cands: &[Vec<bool>], i: usize, state: SkyscraperState, memo: &mut HashMap<SkyscraperState, bool>, visited: &mut HashSet<SkyscraperState>, ret: &mut [Vec<bool>]) {
+        if i == cands.len() || !visited.insert(state) {
+            return;
+        }
+        for h in (0..cands[i].len()).filter(|h| cands[i][*h] && state.0 & (1 << h) == 0) {
+            let next = Self::place(state, h);
+            if self.finishes(cands, i + 1, next, memo) {
+                ret[i][h] = true;
+                self.mark(cands, i + 1, next, memo, visited, ret);
+            }
+        }
+    }
+
+    /// For each cell and digit, whether the edge rules allow it and some order of
+    /// different digits through it shows the clue. Heights go by the digits' values, so the
+    /// search runs over digits sorted from shortest to tallest
+    fn supports(&self, cands: &[Vec<bool>], values: &[i64]) -> Vec<Vec<bool>> {
+        let digits = values.len();
+        let mut by_height: Vec<usize> = (0..digits).collect();
+        by_height.sort_by_key(|z| values[*z]);
+        let heights: Vec<Vec<bool>> = cands.iter().enumerate()
+            .map(|(i, c)| (0..digits).map(|h| c[by_height[h]] && self.edge_rule(digits, cands.len(), i, h)).collect())
+            .collect();
+        let mut found = vec![vec![false; digits]; cands.len()];
+        let mut memo = HashMap::new();
+        self.mark(&heights, 0, (0, None, 0), &mut memo, &mut HashSet::new(), &mut found);
+        found.iter()
+            .map(|f| {
+                let mut ret = vec![false; digits];
+                for (h, z) in by_height.iter().enumerate() {
+                    ret[*z] = f[h];
+                }
+                ret
+            })
+            .collect()
+    }
+}
+
+impl<S : Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for SkyscraperConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
+        let cells = self.edge.cells(board.rows(), board.cols());
+        let values: Vec<i64> = (0..board.digits()).map(|z| board.value(z)).collect();
+        let supports = self.supports(&candidates(board, &cells), &values);
+        prune_by_supports(board, &cells, &supports, || self.name())
+    }
+
+    fn affects(
+        &self,
+        board: &S,
+        v: &Tuple3D
+    ) -> Vec<Tuple3D> {
+        let values: Vec<i64> = (0..board.digits()).map(|z| board.value(z)).collect();
+        support_links(board, &self.edge.cells(board.rows(), board.cols()), v, |cands| self.supports(cands, &values))
+    }
+}
+
+/// Cells a chess move apart can't hold the same digit
+pub struct ChessConstraint {
+    /// What the rule is called, like "anti-knight"
+    pub(crate) name: String,
+    /// (row, column) steps, in every direction the piece can go
+    pub(crate) moves: Vec<(isize, isize)>,
+    /// Moves carry on in a line to the edge of the grid, like a queen's
+    pub(crate) sliding: bool,
+    /// Only these digits are kept apart, or every digit
+    pub(crate) digits: Option<Vec<usize>>,
+}
+
+impl Clone for ChessConstraint {
+    fn clone(&self) -> Self {
+        ChessConstraint{ name: self.name.clone(), moves: self.moves.clone(), sliding: self.sliding, digits: self.digits.clone() }
+    }
+
+    fn clone_from(&mut self, source: &Self) where Self: {
+        self.name = source.name.clone();
+        self.moves = source.moves.clone();
+        self.sliding = source.sliding;
+        self.digits = source.digits.clone();
+    }
+}
+
+const KING_MOVES: [(isize, isize); 8] = [(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)];
+
+impl ChessConstraint {
+    pub fn knight() -> Self {
+        let moves = vec![(-2, -1), (-2, 1), (-1, -2), (-1, 2), (1, -2), (1, 2), (2, -1), (2, 1)];
+        ChessConstraint { name: String::from("anti-knight"), moves, sliding: false, digits: None }
+    }
+
+    pub fn king() -> Self {
+        ChessConstraint { name: String::from("anti-king"), moves: KING_MOVES.to_vec(), sliding: false, digits: None }
+    }
+
+    /// Moves of any (row, column) offsets, each taken in every direction
+    pub fn offsets(offsets: &[(isize, isize)]) -> Self {
+        let mut moves = vec![];
+        for (dx, dy) in offsets {
+            for m in [(*dx, *dy), (-dx, *dy), (*dx, -dy), (-dx, -dy)] {
+                if !moves.contains(&m) {
+                    moves.push(m);
+                }
+            }
+        }
+        ChessConstraint { name: String::from("anti-move"), moves, sliding: false, digits: None }
+    }
+
+    /// Queen moves, for just the given digits
+    pub fn queen(digits: Vec<usize>) -> Self {
+        ChessConstraint { name: String::from("anti-queen"), moves: KING_MOVES.to_vec(), sliding: true, digits: Some(digits) }
+    }
+
+    fn applies(&self, z: usize) -> bool {
+        self.digits.as_ref().is_none_or(|d| d.contains(&z))
+    }
+
+    /// Cells one move from (x, y)
+    fn reach(&self, rows: usize, cols: usize, (x, y): (usize, usize)) -> Vec<(usize, usize)> {
+        let mut ret = vec![];
+        for (dx, dy) in &self.moves {
+            let (mut x_, mut y_) = (x as isize + dx, y as isize + dy);
+            while x_ >= 0 && y_ >= 0 && x_ < rows as isize && y_ < cols as isize {
+                ret.push((x_ as usize, y_ as usize));
+                if !self.sliding {
+                    break;
+                }
+                x_ += dx;
+                y_ += dy;
+            }
+        }
+        ret
+    }
+}
+
+impl<S : Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for ChessConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
+        let mut did = false;
+        for v in &board.positions() {
+            let (x, y, z) = v.pos;
+            if board.get(v) != True || !self.applies(z) {
+                continue;
+            }
+            for (x_, y_) in self.reach(board.rows(), board.cols(), (x, y)) {
+                let to_rem = Tuple3D::from((x_, y_, z));
+                *(board.getm(&to_rem)) = match board.get(&to_rem) {
+                    True => return Err(Contradiction::new(format!("{} from {}", self.name, cell_name(x, y)), vec![*v, to_rem])),
+                    False => False,
+                    Poss => {
+                        did = true;
+                        False
+                    }
+                };
+            }
+        }
+        Ok(did)
+    }
+
+    /// The same digit a move away
+    fn affects(
+        &self,
+        board: &S,
+        v: &Tuple3D
+    ) -> Vec<Tuple3D> {
+        let mut ret = Vec::new();
+        let (x, y, z) = v.pos;
+        if board.get(v) != Poss || !self.applies(z) {
+            return ret;
+        }
+        for (x_, y_) in self.reach(board.rows(), board.cols(), (x, y)) {
+            let ret_tup = Tuple3D::from((x_, y_, z));
+            if board.get(&ret_tup) == Poss {
+                ret.push(ret_tup);
+            }
+        }
+        ret
+    }
+}
+
+/// A circle on the corner of four cells, listing digits that appear among them.
+/// A digit listed twice appears twice
+pub struct QuadrupleConstraint {
+    pub(crate) cells: Vec<(usize, usize)>,
+    pub(crate) digits: Vec<usize>,
+}
+
+impl Clone for QuadrupleConstraint {
+    fn clone(&self) -> Self {
+        QuadrupleConstraint{ cells: self.cells.clone(), digits: self.digits.clone() }
+    }
+
+    fn clone_from(&mut self, source: &Self) where Self: {
+        self.cells = source.cells.clone();
+        self.digits = source.digits.clone();
+    }
+}
+
+impl QuadrupleConstraint {
+    fn name(&self) -> String {
+        match self.cells.first() {
+            Some((x, y)) => format!("quadruple at {}", cell_name(*x, *y)),
+            None => String::from("empty quadruple"),
+        }
+    }
+
+    /// Whether every listed digit appears often enough
+    fn satisfied(&self, combo: &[usize]) -> bool {
+        self.digits.iter().all(|d| {
+            combo.iter().filter(|z| *z == d).count() >= self.digits.iter().filter(|z| *z == d).count()
+        })
+    }
+
+    /// Tries every digit for the cells from `i` on, keeping cells in a row or column
+    /// different, and marks the digits of each filling that shows the clue
+    fn fill(&self, cands: &[Vec<bool>], i: usize, combo: &mut Vec<usize>, ret: &mut [Vec<bool>]) {
+        if i == cands.len() {
+            if self.satisfied(combo) {
+                for (j, z) in combo.iter().enumerate() {
+                    ret[j][*z] = true;
+                }
+            }
+            return;
+        }
+        let (x, y) = self.cells[i];
+        for z in (0..cands[i].len()).filter(|z| cands[i][*z]) {
+            let clash = combo.iter().enumerate().any(|(j, z_)| {
+                let (x_, y_) = self.cells[j];
+                *z_ == z && (x_ == x || y_ == y)
+            });
+            if clash {
+                continue;
+            }
+            combo.push(z);
+            self.fill(cands, i + 1, combo, ret);
+            combo.pop();
+        }
+    }
+
+    fn supports(&self, cands: &[Vec<bool>]) -> Vec<Vec<bool>> {
+        let digits = cands.first().map_or(0, |c| c.len());
+        let mut ret = vec![vec![false; digits]; cands.len()];
+        self.fill(cands, 0, &mut vec![], &mut ret);
+        ret
+    }
+}
+
+impl<S : Board<Tuple3D> + Grid> Constraint<Tuple3D, S> for QuadrupleConstraint {
+    fn apply(&self, board: &mut S) -> Result<bool, Contradiction<Tuple3D>> {
+        let supports = self.supports(&candidates(board, &self.cells));
+        prune_by_supports(board, &self.cells, &supports, || self.name())
+    }
+
+    fn affects(
+        &self,
+        board: &S,
+        v: &Tuple3D
+    ) -> Vec<Tuple3D> {
+        support_links(board, &self.cells, v, |cands| self.supports(cands))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::SdkBoard;
+    use crate::layout::Side;
+
+    /// An empty board with digits 1 up to `digits`
+    fn board(rows: usize, cols: usize, digits: usize) -> SdkBoard {
+        SdkBoard::new(rows, cols, (1..=digits).map(|d| d.to_string()).collect())
+    }
+
+    /// Solves a cell, as it looks once the other digits are cleared from it
+    fn place(board: &mut SdkBoard, x: usize, y: usize, z: usize) {
+        for z_ in 0..board.digits() {
+            *board.getm(&Tuple3D::from((x, y, z_))) = if z_ == z { True } else { False };
+        }
+    }
+
+    /// Digits not yet ruled out of a cell
+    fn left(board: &SdkBoard, x: usize, y: usize) -> Vec<usize> {
+        (0..board.digits()).filter(|z| board.get(&Tuple3D::from((x, y, *z))) != False).collect()
+    }
+
+    #[test]
+    fn killer_cage_keeps_digits_that_reach_the_sum() {
+        let mut b = board(1, 2, 9);
+        let con = KillerCageConstraint { cells: vec![(0, 0), (0, 1)], sum: 3 };
+        assert!(matches!(con.apply(&mut b), Ok(true)));
+        assert_eq!(left(&b, 0, 0), vec![0, 1]);
+        assert_eq!(left(&b, 0, 1), vec![0, 1]);
+        assert!(matches!(con.apply(&mut b), Ok(false)));
+        place(&mut b, 0, 1, 0);
+        assert!(matches!(con.apply(&mut b), Ok(true)));
+        assert_eq!(left(&b, 0, 0), vec![1]);
+
+        // 9 can't repeat, so two cells never reach 18
+        let too_big = KillerCageConstraint { cells: vec![(0, 0), (0, 1)], sum: 18 };
+        let Err(c) = too_big.apply(&mut board(1, 2, 9)) else { panic!("a cage of 18 fit in two cells") };
+        assert_eq!(c.rule, "cage of 18 at r1c1");
+    }
+
+    #[test]
+    fn arrow_keeps_circle_and_arrow_in_range() {
+        let mut b = board(1, 3, 9);
+        let con = ArrowConstraint { circle: vec![(0, 0)], arrow: vec![(0, 1), (0, 2)] };
+        assert!(matches!(con.apply(&mut b), Ok(true)));
+        assert_eq!(left(&b, 0, 0), (1..9).collect::<Vec<_>>());
+        assert_eq!(left(&b, 0, 1), (0..8).collect::<Vec<_>>());
+
+        let mut b = board(1, 3, 9);
+        place(&mut b, 0, 0, 0);
+        let Err(c) = con.apply(&mut b) else { panic!("two arrow cells added up to 1") };
+        assert_eq!(c.rule, "arrow from r1c1");
+    }
+
+    #[test]
+    fn pill_reads_its_cells_as_one_number() {
+        // A pill of 1 and 2 makes 12, which two arrow cells can only reach with 3 and 9 or more
+        let mut b = board(1, 4, 9);
+        place(&mut b, 0, 0, 0);
+        place(&mut b, 0, 1, 1);
+        let con = ArrowConstraint { circle: vec![(0, 0), (0, 1)], arrow: vec![(0, 2), (0, 3)] };
+        assert!(matches!(con.apply(&mut b), Ok(true)));
+        assert_eq!(left(&b, 0, 2), (2..9).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn kropki_dots_keep_supported_digits() {
+        let mut b = board(1, 2, 9);
+        place(&mut b, 0, 0, 2);
+        let black = PairConstraint { a: (0, 0), b: (0, 1), relations: vec![Relation::Ratio(2)], negated: false };
+        assert!(matches!(black.apply(&mut b), Ok(true)));
+        assert_eq!(left(&b, 0, 1), vec![5]);
+
+        // 1 and 3 are two apart, not one
+        let mut b = board(1, 2, 9);
+        place(&mut b, 0, 0, 0);
+        place(&mut b, 0, 1, 2);
+        let white = PairConstraint { a: (0, 0), b: (0, 1), relations: vec![Relation::Difference(1)], negated: false };
+        let Err(c) = white.apply(&mut b) else { panic!("a white dot allowed 1 and 3") };
+        assert_eq!(c.rule, "r1c1 and r1c2 1 apart");
+    }
+
+    #[test]
+    fn xv_markers_and_their_negative() {
+        let mut b = board(1, 2, 9);
+        place(&mut b, 0, 0, 0);
+        let v = PairConstraint { a: (0, 0), b: (0, 1), relations: vec![Relation::Sum(5)], negated: false };
+        assert!(matches!(v.apply(&mut b), Ok(true)));
+        assert_eq!(left(&b, 0, 1), vec![3]);
+
+        // Without an X between them, a 1 can't sit next to a 9
+        let mut b = board(1, 2, 9);
+        place(&mut b, 0, 0, 0);
+        let not_x = PairConstraint { a: (0, 0), b: (0, 1), relations: vec![Relation::Sum(10)], negated: true };
+        assert!(matches!(not_x.apply(&mut b), Ok(true)));
+        assert_eq!(left(&b, 0, 1), (0..8).collect::<Vec<_>>());
+        place(&mut b, 0, 1, 8);
+        let Err(c) = not_x.apply(&mut b) else { panic!("1 and 9 were allowed without an X") };
+        assert_eq!(c.rule, "r1c1 and r1c2 not adding to 10");
+    }
+
+    #[test]
+    fn german_whispers_rule_out_five() {
+        let mut b = board(1, 2, 9);
+        let line = whisper_constraint(vec![(0, 0), (0, 1)], 5);
+        assert_eq!(line.len(), 1);
+        assert!(matches!(line[0].apply(&mut b), Ok(true)));
+        assert_eq!(left(&b, 0, 0), vec![0, 1, 2, 3, 5, 6, 7, 8]);
+        assert_eq!(left(&b, 0, 1), vec![0, 1, 2, 3, 5, 6, 7, 8]);
+        place(&mut b, 0, 0, 0);
+        place(&mut b, 0, 1, 3);
+        assert!(line[0].apply(&mut b).is_err());
+    }
+
+    #[test]
+    fn lines_too_short_for_a_pair_add_nothing() {
+        assert!(whisper_constraint(vec![], 5).is_empty());
+        assert!(whisper_constraint(vec![(0, 0)], 4).is_empty());
+        assert!(thermo_constraint(vec![]).is_empty());
+        assert!(palindrome_constraint(vec![]).is_empty());
+        assert!(palindrome_constraint(vec![(0, 0)]).is_empty());
+    }
+
+    #[test]
+    fn renban_keeps_a_run() {
+        let mut b = board(1, 3, 9);
+        place(&mut b, 0, 0, 0);
+        let con = RenbanConstraint { cells: vec![(0, 0), (0, 1), (0, 2)] };
+        assert!(matches!(con.apply(&mut b), Ok(true)));
+        assert_eq!(left(&b, 0, 1), vec![1, 2]);
+        assert_eq!(left(&b, 0, 2), vec![1, 2]);
+        // A 9 leaves no run of three through the 1
+        place(&mut b, 0, 1, 8);
+        let Err(c) = con.apply(&mut b) else { panic!("1 and 9 fit in a renban of three") };
+        assert_eq!(c.rule, "renban from r1c1");
+    }
+
+    #[test]
+    fn palindrome_mirrors_digits() {
+        let mut b = board(1, 3, 9);
+        place(&mut b, 0, 0, 2);
+        let line = palindrome_constraint(vec![(0, 0), (0, 1), (0, 2)]);
+        assert_eq!(line.len(), 1, "the middle cell pairs with nothing");
+        assert!(matches!(line[0].apply(&mut b), Ok(true)));
+        assert_eq!(left(&b, 0, 2), vec![2]);
+        assert_eq!(left(&b, 0, 1).len(), 9);
+        place(&mut b, 0, 2, 3);
+        assert!(line[0].apply(&mut b).is_err());
+    }
+
+    #[test]
+    fn between_line_stays_between_its_ends() {
+        let mut b = board(1, 3, 9);
+        place(&mut b, 0, 0, 1);
+        place(&mut b, 0, 2, 4);
+        let con = BetweenConstraint { ends: ((0, 0), (0, 2)), cells: vec![(0, 1)] };
+        assert!(matches!(con.apply(&mut b), Ok(true)));
+        assert_eq!(left(&b, 0, 1), vec![2, 3]);
+
+        // Ends of 4 and 5 leave nothing strictly between them
+        let mut b = board(1, 3, 9);
+        place(&mut b, 0, 0, 3);
+        place(&mut b, 0, 2, 4);
+        let Err(c) = con.apply(&mut b) else { panic!("a digit fit between 4 and 5") };
+        assert_eq!(c.rule, "between line from r1c1 to r1c3");
+    }
+
+    #[test]
+    fn region_sum_segments_match() {
+        let mut b = board(1, 3, 9);
+        place(&mut b, 0, 0, 1);
+        let con = RegionSumConstraint { segments: vec![vec![(0, 0)], vec![(0, 1), (0, 2)]] };
+        assert!(matches!(con.apply(&mut b), Ok(true)));
+        assert_eq!((left(&b, 0, 1), left(&b, 0, 2)), (vec![0], vec![0]));
+        assert!(matches!(con.apply(&mut b), Ok(false)));
+
+        // Two cells never add up to 1
+        let mut b = board(1, 3, 9);
+        place(&mut b, 0, 0, 0);
+        assert!(con.apply(&mut b).is_err());
+    }
+
+    #[test]
+    fn sandwich_places_crusts_around_the_filling() {
+        // With digits 1-4 the crusts are 1 and 4, so a sum of 5 needs both other digits inside
+        let mut b = board(1, 4, 4);
+        let con = SandwichConstraint { edge: Edge { side: Side::Left, index: 0 }, sum: 5 };
+        assert!(matches!(con.apply(&mut b), Ok(true)));
+        let cells: Vec<Vec<usize>> = (0..4).map(|y| left(&b, 0, y)).collect();
+        assert_eq!(cells, vec![vec![0, 3], vec![1, 2], vec![1, 2], vec![0, 3]]);
+
+        let con = SandwichConstraint { edge: Edge { side: Side::Left, index: 0 }, sum: 4 };
+        let Err(c) = con.apply(&mut board(1, 4, 4)) else { panic!("a sandwich of 4 fit between 1 and 4") };
+        assert_eq!(c.rule, "sandwich of 4 left of row 1");
+    }
+
+    #[test]
+    fn little_killer_lets_digits_repeat() {
+        // Two 1s are the only way to make 2, which a cage would not allow
+        let mut b = board(2, 2, 9);
+        let con = LittleKillerConstraint { cells: vec![(0, 0), (1, 1)], sum: 2 };
+        assert!(matches!(con.apply(&mut b), Ok(true)));
+        assert_eq!(left(&b, 0, 0), vec![0]);
+        assert_eq!(left(&b, 1, 1), vec![0]);
+
+        let con = LittleKillerConstraint { cells: vec![(0, 0), (1, 1)], sum: 19 };
+        let Err(c) = con.apply(&mut board(2, 2, 9)) else { panic!("two digits reached 19") };
+        assert_eq!(c.rule, "little killer of 19 from r1c1");
+    }
+
+    #[test]
+    fn x_sum_counts_off_the_first_digit() {
+        // A leading 1 sums to 1 and a leading 3 already passes 3, so it has to be 2 then 1
+        let mut b = board(1, 4, 4);
+        let con = XSumConstraint { edge: Edge { side: Side::Left, index: 0 }, sum: 3 };
+        assert!(matches!(con.apply(&mut b), Ok(true)));
+        assert_eq!(left(&b, 0, 0), vec![1]);
+        assert_eq!(left(&b, 0, 1), vec![0]);
+        assert_eq!(left(&b, 0, 2).len(), 4);
+
+        let con = XSumConstraint { edge: Edge { side: Side::Left, index: 0 }, sum: 2 };
+        assert!(con.apply(&mut board(1, 4, 4)).is_err());
+    }
+
+    #[test]
+    fn skyscraper_clues_order_the_line() {
+        let mut b = board(1, 4, 4);
+        let con = SkyscraperConstraint { edge: Edge { side: Side::Left, index: 0 }, count: 4 };
+        assert!(matches!(con.apply(&mut b), Ok(true)));
+        assert_eq!((0..4).map(|y| left(&b, 0, y)).collect::<Vec<_>>(), vec![vec![0], vec![1], vec![2], vec![3]]);
+
+        // A 1 in front hides nothing, so at least two are seen
+        let mut b = board(1, 4, 4);
+        place(&mut b, 0, 0, 0);
+        let con = SkyscraperConstraint { edge: Edge { side: Side::Left, index: 0 }, count: 1 };
+        let Err(c) = con.apply(&mut b) else { panic!("a 1 in front hid the rest") };
+        assert_eq!(c.rule, "skyscraper of 1 left of row 1");
+    }
+
+    #[test]
+    fn skyscraper_heights_are_digit_values() {
+        // Listed out of order, so the first digit is the tallest
+        let mut b = SdkBoard::new(1, 3, vec![String::from("3"), String::from("1"), String::from("2")]);
+        let con = SkyscraperConstraint { edge: Edge { side: Side::Left, index: 0 }, count: 1 };
+        assert!(matches!(con.apply(&mut b), Ok(true)));
+        assert_eq!(left(&b, 0, 0), vec![0]);
+    }
+
+    #[test]
+    fn anti_knight_clears_a_knight_move_away() {
+        let mut b = board(3, 3, 9);
+        place(&mut b, 0, 0, 0);
+        let con = ChessConstraint::knight();
+        assert!(matches!(con.apply(&mut b), Ok(true)));
+        assert!(!left(&b, 1, 2).contains(&0));
+        assert!(!left(&b, 2, 1).contains(&0));
+        assert!(left(&b, 1, 1).contains(&0));
+        assert!(matches!(con.apply(&mut b), Ok(false)));
+
+        place(&mut b, 1, 2, 0);
+        let Err(c) = con.apply(&mut b) else { panic!("two 1s sat a knight's move apart") };
+        assert_eq!(c.rule, "anti-knight from r1c1");
+    }
+
+    #[test]
+    fn anti_queen_slides_for_its_digits_only() {
+        let mut b = board(4, 4, 4);
+        place(&mut b, 0, 0, 3);
+        place(&mut b, 0, 3, 0);
+        assert!(matches!(ChessConstraint::queen(vec![3]).apply(&mut b), Ok(true)));
+        assert!(!left(&b, 3, 3).contains(&3));
+        assert!(left(&b, 3, 0).contains(&0));
+    }
+
+    #[test]
+    fn offsets_go_in_every_direction() {
+        let mut moves = ChessConstraint::offsets(&[(1, 2), (2, 1)]).moves;
+        let mut knight = ChessConstraint::knight().moves;
+        moves.sort();
+        knight.sort();
+        assert_eq!(moves, knight);
+    }
+
+    #[test]
+    fn nonconsecutive_rules_out_neighbouring_digits() {
+        let mut b = board(1, 2, 9);
+        place(&mut b, 0, 0, 4);
+        let con = PairConstraint { a: (0, 0), b: (0, 1), relations: vec![Relation::Difference(1)], negated: true };
+        assert!(matches!(con.apply(&mut b), Ok(true)));
+        assert_eq!(left(&b, 0, 1), vec![0, 1, 2, 4, 6, 7, 8]);
+        place(&mut b, 0, 1, 5);
+        let Err(c) = con.apply(&mut b) else { panic!("5 and 6 were allowed side by side") };
+        assert_eq!(c.rule, "r1c1 and r1c2 not 1 apart");
+    }
+
+    #[test]
+    fn digit_set_removes_other_digits() {
+        let mut b = board(1, 1, 4);
+        let con = DigitSetConstraint { name: String::from("odd"), cell: (0, 0), digits: vec![0, 2] };
+        assert!(matches!(con.apply(&mut b), Ok(true)));
+        assert_eq!(left(&b, 0, 0), vec![0, 2]);
+        assert!(matches!(con.apply(&mut b), Ok(false)));
+
+        place(&mut b, 0, 0, 1);
+        let Err(c) = con.apply(&mut b) else { panic!("a 2 sat in an odd cell") };
+        assert_eq!(c.cells.iter().map(|v| v.pos).collect::<Vec<_>>(), vec![(0, 0, 1)]);
+    }
+
+    #[test]
+    fn digit_set_names_the_cell_with_no_digit_left() {
+        let mut b = board(1, 1, 4);
+        for z in [1, 3] {
+            *b.getm(&Tuple3D::from((0, 0, z))) = False;
+        }
+        let con = DigitSetConstraint { name: String::from("even"), cell: (0, 0), digits: vec![1, 3] };
+        let Err(c) = con.apply(&mut b) else { panic!("an even cell had no even digit left") };
+        assert_eq!(c.rule, "even cell r1c1");
+        assert_eq!(c.cells.iter().map(|v| v.pos).collect::<Vec<_>>(), vec![(0, 0, 1), (0, 0, 3)]);
+    }
+
+    #[test]
+    fn quadruple_keeps_its_digits_around_the_corner() {
+        // Four listed digits fill the four cells, so nothing else fits
+        let mut b = board(2, 2, 9);
+        let con = QuadrupleConstraint { cells: vec![(0, 0), (0, 1), (1, 0), (1, 1)], digits: vec![0, 1, 2, 3] };
+        assert!(matches!(con.apply(&mut b), Ok(true)));
+        assert_eq!(left(&b, 1, 1), vec![0, 1, 2, 3]);
+
+        // 1, 1, 3 and 4 take all four cells, leaving no room for the 2
+        let mut b = board(2, 2, 9);
+        place(&mut b, 0, 0, 0);
+        place(&mut b, 1, 1, 1);
+        let twice = QuadrupleConstraint { cells: vec![(0, 0), (0, 1), (1, 0), (1, 1)], digits: vec![0, 0, 2, 3] };
+        let Err(c) = twice.apply(&mut b) else { panic!("two 1s fit beside a 2") };
+        assert_eq!(c.rule, "quadruple at r1c1");
+    }
+}
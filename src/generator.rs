@@ -0,0 +1,206 @@
+use crate::board::LogicVal::True;
+use crate::board::{Board, Grid, Puzzle, SdkBoard, SolutionCount, Tuple3D};
+use crate::constraints::{Constraint, Contradiction, GivenConstraint};
+use crate::techniques::Rating;
+
+/// Small xorshift generator, so a puzzle can be made again from its seed
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck on 0
+        Rng(seed ^ 0x9E37_79B9_7F4A_7C15)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number from 0 up to but not including `n`
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    pub fn shuffle<I>(&mut self, items: &mut [I]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+/// Guesses allowed when checking a puzzle is unique before the given stays in
+const UNIQUE_GUESSES: usize = 1000;
+
+/// Which cells have to be given or removed together
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Symmetry {
+    None,
+    /// Turning the grid half way round
+    Rotational,
+    /// Reflecting left to right
+    Mirror,
+    /// Reflecting across the main diagonal, for square grids
+    Diagonal,
+}
+
+impl Symmetry {
+    /// The cell and its images, without repeats
+    fn orbit(&self, rows: usize, cols: usize, (x, y): (usize, usize)) -> Vec<(usize, usize)> {
+        let other = match self {
+            Symmetry::None => (x, y),
+            Symmetry::Rotational => (rows - 1 - x, cols - 1 - y),
+            Symmetry::Mirror => (x, cols - 1 - y),
+            Symmetry::Diagonal => (y, x),
+        };
+        if other == (x, y) { vec![(x, y)] } else { vec![(x, y), other] }
+    }
+}
+
+/// A generated puzzle
+pub struct Generated {
+    /// Givens as (row, column, digit)
+    pub givens: Vec<(usize, usize, usize)>,
+    pub solution: SdkBoard,
+    pub rating: Rating,
+    /// Whether the difficulty target was reached, if there was one. Removing givens can run
+    /// out before the puzzle gets that hard
+    pub target_met: Option<bool>,
+}
+
+/// Makes puzzles for any set of rules: fills a random solution, then takes givens away
+/// for as long as the solution stays unique
+pub struct Generator<'a> {
+    blank: SdkBoard,
+    /// Builds the puzzle's rules without any givens
+    rules: &'a dyn Fn() -> Vec<Box<dyn Constraint<Tuple3D, SdkBoard>>>,
+    pub symmetry: Symmetry,
+    /// Stop once the puzzle's difficulty score reaches this, only keeping puzzles the
+    /// techniques can finish. Without it givens are removed until none can go
+    pub target: Option<usize>,
+    rng: Rng,
+}
+
+impl<'a> Generator<'a> {
+    pub fn new(blank: SdkBoard, rules: &'a dyn Fn() -> Vec<Box<dyn Constraint<Tuple3D, SdkBoard>>>, seed: u64) -> Self {
+        Generator { blank, rules, symmetry: Symmetry::None, target: None, rng: Rng::new(seed) }
+    }
+
+    fn puzzle(&self, givens: &[(usize, usize, usize)]) -> Puzzle<Tuple3D, SdkBoard> {
+        let mut puzzle = Puzzle::init(self.blank.clone());
+        puzzle.constraints = (self.rules)();
+        for pos in givens {
+            puzzle.constraints.push(Box::new(GivenConstraint { pos: *pos }));
+        }
+        puzzle
+    }
+
+    /// Fills the puzzle with a random solution, guessing digits in a random order.
+    /// Gives up with None once `budget` guesses are spent, since backing out of an
+    /// unlucky early guess can take far longer than starting again
+    fn fill(&mut self, puzzle: &mut Puzzle<Tuple3D, SdkBoard>, budget: &mut usize) -> Option<bool> {
+        if puzzle.propagate().is_err() {
+            return Some(false);
+        }
+        match puzzle.most_constrained() {
+            None => Some(true),
+            Some(mut cands) => {
+                self.rng.shuffle(&mut cands);
+                let backup = puzzle.board.clone();
+                for v in cands {
+                    if *budget == 0 {
+                        return None;
+                    }
+                    *budget -= 1;
+                    *(puzzle.board.getm(&v)) = True;
+                    if self.fill(puzzle, budget)? {
+                        return Some(true);
+                    }
+                    puzzle.board = backup.clone();
+                }
+                Some(false)
+            }
+        }
+    }
+
+    /// Makes a puzzle, or None if the rules have no solution at all. Fails if the rules
+    /// break on the finished givens, which the search should have ruled out
+    pub fn generate(&mut self) -> Result<Option<Generated>, Contradiction<Tuple3D>> {
+        let mut budget = 100;
+        let solution = loop {
+            let mut puzzle = self.puzzle(&[]);
+            match self.fill(&mut puzzle, &mut budget.clone()) {
+                Some(true) => break puzzle.board,
+                Some(false) => return Ok(None),
+                None => budget *= 2,
+            }
+        };
+        let mut givens: Vec<(usize, usize, usize)> = solution.positions().into_iter()
+            .filter(|v| solution.get(v) == True)
+            .map(|v| v.pos)
+            .collect();
+
+        let mut order: Vec<(usize, usize)> = givens.iter().map(|(x, y, _)| (*x, *y)).collect();
+        self.rng.shuffle(&mut order);
+        for cell in order {
+            let orbit = self.symmetry.orbit(solution.rows(), solution.cols(), cell);
+            let fewer: Vec<(usize, usize, usize)> = givens.iter().copied()
+                .filter(|(x, y, _)| !orbit.contains(&(*x, *y)))
+                .collect();
+            if fewer.len() == givens.len() {
+                continue;
+            }
+            // A puzzle that takes too long to prove unique keeps the given
+            match self.puzzle(&fewer).count_solutions_within(2, UNIQUE_GUESSES) {
+                Some(s) if s.count == SolutionCount::Exactly(1) => {}
+                _ => continue,
+            }
+            let Some(target) = self.target else {
+                givens = fewer;
+                continue;
+            };
+            match self.puzzle(&fewer).rate() {
+                Ok(rating) if rating.solved => {
+                    givens = fewer;
+                    if rating.score >= target {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let rating = self.puzzle(&givens).rate()?;
+        let target_met = self.target.map(|t| rating.solved && rating.score >= t);
+        Ok(Some(Generated { givens, solution, rating, target_met }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::puzzle_file;
+
+    fn generate(src: &str, target: Option<usize>) -> Generated {
+        let file = puzzle_file::parse(src).unwrap();
+        let rules = || file.constraints();
+        let mut generator = Generator::new(file.board(), &rules, 1);
+        generator.target = target;
+        let Ok(Some(made)) = generator.generate() else { panic!("no puzzle was made") };
+        made
+    }
+
+    #[test]
+    fn an_unreachable_target_is_reported() {
+        let made = generate("size 4\n", Some(100_000));
+        assert_eq!(made.target_met, Some(false));
+        assert!(made.rating.solved);
+    }
+
+    #[test]
+    fn no_target_reports_nothing() {
+        assert_eq!(generate("size 4\n", None).target_met, None);
+    }
+}
@@ -0,0 +1,374 @@
+use std::fmt::{Display, Formatter};
+use crate::board::{Board, Grid, Tuple3D};
+use crate::constraints::{CellConstraint, CellExistConstraint, ColExistConstraint, ColUniqueConstraint, Constraint, DigitExistConstraint, DigitUniqueConstraint, RowExistConstraint, RowUniqueConstraint};
+
+#[derive(Debug, PartialEq)]
+pub enum LayoutError {
+    /// Boxes of this width and height don't cover the grid exactly
+    DoesNotTile { rows: usize, cols: usize, width: usize, height: usize },
+    /// No box shape other than a full row fits this size
+    NoBoxes { size: usize },
+    /// A box has more cells than there are digits, so a digit would repeat
+    TooManyCells { width: usize, height: usize, digits: usize },
+    /// The preset's regions can't be drawn on a grid this shape
+    PresetDoesNotFit { preset: Preset, rows: usize, cols: usize },
+    /// The preset's regions are placed by the boxes, and the grid has none
+    PresetNeedsBoxes { preset: Preset },
+}
+
+impl Display for LayoutError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LayoutError::DoesNotTile { rows, cols, width, height } =>
+                write!(f, "{}x{} boxes don't tile a {}x{} grid", width, height, rows, cols),
+            LayoutError::NoBoxes { size } =>
+                write!(f, "a {}x{} grid can't be split into boxes", size, size),
+            LayoutError::TooManyCells { width, height, digits } =>
+                write!(f, "{}x{} boxes have more cells than the {} digits", width, height, digits),
+            LayoutError::PresetDoesNotFit { preset, rows, cols } =>
+                write!(f, "{} regions don't fit a {}x{} grid, they need {}", preset, rows, cols, preset.needs()),
+            LayoutError::PresetNeedsBoxes { preset } =>
+                write!(f, "{} regions need boxes", preset),
+        }
+    }
+}
+
+/// A grid split into equal boxes in which no digit repeats
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoxLayout {
+    pub rows: usize,
+    pub cols: usize,
+    /// Columns in a box
+    pub width: usize,
+    /// Rows in a box
+    pub height: usize,
+}
+
+impl BoxLayout {
+    pub fn new(rows: usize, cols: usize, width: usize, height: usize) -> Result<Self, LayoutError> {
+        if width == 0 || height == 0 || !rows.is_multiple_of(height) || !cols.is_multiple_of(width) {
+            return Err(LayoutError::DoesNotTile { rows, cols, width, height });
+        }
+        Ok(BoxLayout { rows, cols, width, height })
+    }
+
+    /// The usual boxes for a square grid: as square as possible and wider than they are tall,
+    /// so 9 gives 3x3, 6 gives 3x2 and 12 gives 4x3
+    pub fn standard(size: usize) -> Result<Self, LayoutError> {
+        let height = (1..=size).take_while(|h| h * h <= size).filter(|h| size.is_multiple_of(*h)).last();
+        match height {
+            Some(h) if h > 1 || size == 1 => BoxLayout::new(size, size, size / h, h),
+            _ => Err(LayoutError::NoBoxes { size }),
+        }
+    }
+
+    /// Cells of every box, left to right then top to bottom
+    pub fn regions(&self) -> Vec<Vec<(usize, usize)>> {
+        let mut ret = vec![];
+        for bx in 0..(self.rows / self.height) {
+            for by in 0..(self.cols / self.width) {
+                let mut cells = vec![];
+                for x in 0..self.height {
+                    for y in 0..self.width {
+                        cells.push((bx * self.height + x, by * self.width + y));
+                    }
+                }
+                ret.push(cells);
+            }
+        }
+        ret
+    }
+
+    /// Checks a box has room for no more digits than there are
+    pub fn check_digits(&self, digits: usize) -> Result<(), LayoutError> {
+        if self.width * self.height > digits {
+            return Err(LayoutError::TooManyCells { width: self.width, height: self.height, digits });
+        }
+        Ok(())
+    }
+
+    /// Row, column and box constraints for a sudoku with these boxes and `digits` digits.
+    /// Boxes only need every digit when they have a cell for each one
+    pub(crate) fn constraints<S: Board<Tuple3D> + Grid>(&self, digits: usize) -> Vec<Box<dyn Constraint<Tuple3D, S>>> {
+        let mut cons = latin_square(self.rows, self.cols, digits);
+        for cells in self.regions() {
+            if cells.len() == digits {
+                cons.push(Box::new(CellExistConstraint { cells: cells.clone() }));
+            }
+            cons.push(Box::new(CellConstraint { cells }));
+        }
+        cons
+    }
+}
+
+/// Common sets of extra regions in which no digit repeats
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Preset {
+    /// Both main diagonals of a square grid, as in Sudoku-X
+    Diagonals,
+    /// Box shaped windows between the boxes, also called hyper sudoku
+    Windoku,
+    /// Cells in the same place in every box
+    DisjointGroups,
+    /// Diagonals one cell and half the grid away from the main ones
+    Argyle,
+    /// Nine cells in a star around the centre of a 9x9 grid
+    Asterisk,
+    /// The middle cell of every box
+    CentreDot,
+}
+
+impl Display for Preset {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Preset::Diagonals => write!(f, "diagonal"),
+            Preset::Windoku => write!(f, "windoku"),
+            Preset::DisjointGroups => write!(f, "disjoint group"),
+            Preset::Argyle => write!(f, "argyle"),
+            Preset::Asterisk => write!(f, "asterisk"),
+            Preset::CentreDot => write!(f, "centre dot"),
+        }
+    }
+}
+
+impl Preset {
+    /// What the grid needs for the preset's regions to be drawn
+    pub fn needs(&self) -> &'static str {
+        match self {
+            Preset::Diagonals | Preset::Argyle => "a square grid",
+            Preset::Windoku => "room for a window between the boxes",
+            Preset::DisjointGroups => "boxes",
+            Preset::Asterisk => "a 9x9 grid",
+            Preset::CentreDot => "boxes with an odd width and height",
+        }
+    }
+
+    /// The preset's regions on a grid with these boxes, if it has any
+    pub fn regions(&self, rows: usize, cols: usize, boxes: Option<&BoxLayout>) -> Result<Vec<Vec<(usize, usize)>>, LayoutError> {
+        let misfit = LayoutError::PresetDoesNotFit { preset: *self, rows, cols };
+        let square = rows == cols;
+        let n = rows;
+        let ret: Vec<Vec<(usize, usize)>> = match self {
+            Preset::Diagonals if square => vec![
+                (0..n).map(|i| (i, i)).collect(),
+                (0..n).map(|i| (i, n - 1 - i)).collect(),
+            ],
+            Preset::Argyle if square => {
+                let mut ret = vec![];
+                let mut offsets = vec![1, n / 2];
+                offsets.dedup();
+                for k in offsets.into_iter().filter(|k| *k > 0 && *k < n) {
+                    ret.push((0..n - k).map(|i| (i, i + k)).collect());
+                    ret.push((0..n - k).map(|i| (i + k, i)).collect());
+                    ret.push((0..n - k).map(|i| (i, n - 1 - k - i)).collect());
+                    ret.push((0..n - k).map(|i| (i + k, n - 1 - i)).collect());
+                }
+                ret
+            }
+            Preset::Asterisk if rows == 9 && cols == 9 => vec![
+                vec![(1, 4), (2, 2), (2, 6), (4, 1), (4, 4), (4, 7), (6, 2), (6, 6), (7, 4)],
+            ],
+            Preset::Windoku | Preset::DisjointGroups | Preset::CentreDot => {
+                let b = boxes.ok_or(LayoutError::PresetNeedsBoxes { preset: *self })?;
+                let mut ret = vec![];
+                match self {
+                    Preset::Windoku => {
+                        // Windows start one cell in, with a gap of one between them
+                        for bx in (1..rows).step_by(b.height + 1).filter(|x| x + b.height < rows) {
+                            for by in (1..cols).step_by(b.width + 1).filter(|y| y + b.width < cols) {
+                                let mut cells = vec![];
+                                for x in 0..b.height {
+                                    for y in 0..b.width {
+                                        cells.push((bx + x, by + y));
+                                    }
+                                }
+                                ret.push(cells);
+                            }
+                        }
+                    }
+                    Preset::DisjointGroups => {
+                        let boxes = b.regions();
+                        for i in 0..(b.width * b.height) {
+                            ret.push(boxes.iter().map(|cells| cells[i]).collect());
+                        }
+                    }
+                    _ => {
+                        if b.width % 2 == 0 || b.height % 2 == 0 {
+                            return Err(misfit);
+                        }
+                        let middle = (b.height / 2) * b.width + b.width / 2;
+                        ret.push(b.regions().iter().map(|cells| cells[middle]).collect());
+                    }
+                }
+                ret
+            }
+            _ => return Err(misfit),
+        };
+        if ret.is_empty() {
+            return Err(misfit);
+        }
+        Ok(ret)
+    }
+}
+
+/// A side of the grid, where clues outside it are written
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+/// A clue outside the grid, beside row or column `index` on `side`, counting from 0
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Edge {
+    pub side: Side,
+    pub index: usize,
+}
+
+impl Edge {
+    /// The row or column the clue looks along, nearest cell first
+    pub fn cells(&self, rows: usize, cols: usize) -> Vec<(usize, usize)> {
+        match self.side {
+            Side::Top => (0..rows).map(|x| (x, self.index)).collect(),
+            Side::Bottom => (0..rows).rev().map(|x| (x, self.index)).collect(),
+            Side::Left => (0..cols).map(|y| (self.index, y)).collect(),
+            Side::Right => (0..cols).rev().map(|y| (self.index, y)).collect(),
+        }
+    }
+
+    /// The diagonal from the clue's corner of the grid heading towards `towards`, which is
+    /// one of the two sides next to the clue's side
+    pub fn diagonal(&self, rows: usize, cols: usize, towards: Side) -> Vec<(usize, usize)> {
+        let (dx, dy): (isize, isize) = match (self.side, towards) {
+            (Side::Top, Side::Left) => (1, -1),
+            (Side::Top, Side::Right) => (1, 1),
+            (Side::Bottom, Side::Left) => (-1, -1),
+            (Side::Bottom, Side::Right) => (-1, 1),
+            (Side::Left, Side::Top) => (-1, 1),
+            (Side::Left, Side::Bottom) => (1, 1),
+            (Side::Right, Side::Top) => (-1, -1),
+            (Side::Right, Side::Bottom) => (1, -1),
+            _ => return vec![],
+        };
+        let (x, y) = self.cells(rows, cols)[0];
+        let (mut x, mut y) = (x as isize, y as isize);
+        let mut ret = vec![];
+        while x >= 0 && y >= 0 && x < rows as isize && y < cols as isize {
+            ret.push((x as usize, y as usize));
+            x += dx;
+            y += dy;
+        }
+        ret
+    }
+}
+
+impl Display for Edge {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.side {
+            Side::Top => write!(f, "above column {}", self.index + 1),
+            Side::Bottom => write!(f, "below column {}", self.index + 1),
+            Side::Left => write!(f, "left of row {}", self.index + 1),
+            Side::Right => write!(f, "right of row {}", self.index + 1),
+        }
+    }
+}
+
+/// Which cells count as neighbours
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Adjacency {
+    /// Sharing an edge
+    Orthogonal,
+    /// Touching at a corner
+    Diagonal,
+    /// Either, like a chess king's move
+    King,
+}
+
+/// Every pair of neighbouring cells, each pair once
+pub fn neighbour_pairs(rows: usize, cols: usize, adjacency: Adjacency) -> Vec<((usize, usize), (usize, usize))> {
+    let steps: &[(isize, isize)] = match adjacency {
+        Adjacency::Orthogonal => &[(1, 0), (0, 1)],
+        Adjacency::Diagonal => &[(1, -1), (1, 1)],
+        Adjacency::King => &[(1, 0), (0, 1), (1, -1), (1, 1)],
+    };
+    let mut ret = vec![];
+    for x in 0..rows {
+        for y in 0..cols {
+            for (dx, dy) in steps {
+                let (x_, y_) = (x as isize + dx, y as isize + dy);
+                if y_ >= 0 && (x_ as usize) < rows && (y_ as usize) < cols {
+                    ret.push(((x, y), (x_ as usize, y_ as usize)));
+                }
+            }
+        }
+    }
+    ret
+}
+
+/// No digit repeats in a row or column and each cell holds one digit.
+/// Rows and columns as long as the digit list also have to hold every digit
+pub(crate) fn latin_square<S: Board<Tuple3D> + Grid>(rows: usize, cols: usize, digits: usize) -> Vec<Box<dyn Constraint<Tuple3D, S>>> {
+    let mut cons: Vec<Box<dyn Constraint<Tuple3D, S>>> = vec![
+        Box::new(RowUniqueConstraint),
+        Box::new(ColUniqueConstraint),
+        Box::new(DigitUniqueConstraint),
+    ];
+    if cols == digits {
+        cons.push(Box::new(RowExistConstraint));
+    }
+    if rows == digits {
+        cons.push(Box::new(ColExistConstraint));
+    }
+    cons.push(Box::new(DigitExistConstraint));
+    cons
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boxes_that_dont_tile_are_rejected() {
+        assert_eq!(BoxLayout::new(9, 9, 2, 3), Err(LayoutError::DoesNotTile { rows: 9, cols: 9, width: 2, height: 3 }));
+        assert_eq!(BoxLayout::new(6, 8, 3, 2), Err(LayoutError::DoesNotTile { rows: 6, cols: 8, width: 3, height: 2 }));
+        assert!(BoxLayout::new(6, 6, 0, 2).is_err());
+        assert!(BoxLayout::new(6, 8, 4, 3).is_ok());
+    }
+
+    #[test]
+    fn standard_boxes_are_as_square_as_fits() {
+        let shape = |size| BoxLayout::standard(size).map(|b| (b.width, b.height));
+        assert_eq!(shape(6), Ok((3, 2)));
+        assert_eq!(shape(8), Ok((4, 2)));
+        assert_eq!(shape(9), Ok((3, 3)));
+        assert_eq!(shape(12), Ok((4, 3)));
+        assert_eq!(shape(7), Err(LayoutError::NoBoxes { size: 7 }));
+    }
+
+    #[test]
+    fn neighbours_are_paired_once() {
+        assert_eq!(neighbour_pairs(3, 3, Adjacency::Orthogonal).len(), 12);
+        assert_eq!(neighbour_pairs(3, 3, Adjacency::Diagonal).len(), 8);
+        assert_eq!(neighbour_pairs(3, 3, Adjacency::King).len(), 20);
+        assert_eq!(neighbour_pairs(2, 2, Adjacency::Diagonal), vec![((0, 0), (1, 1)), ((0, 1), (1, 0))]);
+    }
+
+    #[test]
+    fn presets_say_what_grid_they_need() {
+        let boxes = BoxLayout::standard(6).unwrap();
+        let err = Preset::Asterisk.regions(6, 6, Some(&boxes)).unwrap_err();
+        assert_eq!(err.to_string(), "asterisk regions don't fit a 6x6 grid, they need a 9x9 grid");
+        let err = Preset::CentreDot.regions(6, 6, Some(&boxes)).unwrap_err();
+        assert_eq!(err.to_string(), "centre dot regions don't fit a 6x6 grid, they need boxes with an odd width and height");
+    }
+
+    #[test]
+    fn diagonals_cross_a_square_grid() {
+        let regions = Preset::Diagonals.regions(4, 4, None).unwrap();
+        assert_eq!(regions, vec![vec![(0, 0), (1, 1), (2, 2), (3, 3)], vec![(0, 3), (1, 2), (2, 1), (3, 0)]]);
+        assert_eq!(Preset::Diagonals.regions(4, 6, None), Err(LayoutError::PresetDoesNotFit { preset: Preset::Diagonals, rows: 4, cols: 6 }));
+        assert_eq!(Preset::Windoku.regions(9, 9, None), Err(LayoutError::PresetNeedsBoxes { preset: Preset::Windoku }));
+    }
+}
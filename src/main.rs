@@ -1,27 +1,35 @@
-use crate::board::LogicVal::False;
-use crate::board::{Puzzle, LogicVal, SdkBoard, Tuple3D};
-use crate::constraints::{CellConstraint, ColExistConstraint, ColUniqueConstraint, Constraint, DigitExistConstraint, DigitUniqueConstraint, GivenConstraint, LessThanConstraint, RowExistConstraint, RowUniqueConstraint};
+use std::{env, fs, process};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::board::{Board, Puzzle, SdkBoard, Tuple3D};
+use crate::constraints::{cell_name, Contradiction};
+use crate::puzzle_file::PuzzleFile;
+use crate::generator::{Generator, Symmetry};
+use crate::techniques::{Rating, Step};
 
 mod board;
 mod constraints;
+mod generator;
+mod layout;
+mod puzzle_file;
+mod techniques;
 
 // Goals
 // Offshoots of this one:
 // https://www.puzzle-bridges.com/
 /* Jigsaw
-var cl = "";
+var cl = "size "+Game.puzzleWH+"\ntype jigsaw\n";
 var g = "";
 for (var i=0; i<Game.puzzleWH; i++) {
-  cl += "vec!["
+  cl += "region full"
   for (var j=0; j<Game.puzzleWH; j++) {
     if (Game.currentState.cellStatus[i][j].number != 0) {
-      var num = parseInt(Game.currentState.cellStatus[i][j].number,20)-1;
-      g += "("+i+","+j+","+num+"),\n"
+      var num = parseInt(Game.currentState.cellStatus[i][j].number,20);
+      g += "given r"+(i+1)+"c"+(j+1)+" "+num+"\n"
     }
     ap = Game.areaPoints[i+1][j];
-    cl += "("+ap.row+", "+ap.col+"),"
+    cl += " r"+(ap.row+1)+"c"+(ap.col+1)
   }
-  cl += "],\n"
+  cl += "\n"
 }
 console.log(cl);
 console.log(g);
@@ -29,25 +37,26 @@ document.getElementById("robot").value = 1;
  */
 
 /*  code for futoshiki
-var cp = "";
+var cp = "size "+Game.puzzleWH+"\ntype futoshiki\n";
 var g = "";
 for (var i=0; i<Game.puzzleWH; i++) {
   for (var j=0; j<Game.puzzleWH; j++) {
+    var c = "r"+(i+1)+"c"+(j+1);
     if (Game.conditions[i][j].d) {
-      cp += "(("+(i+1)+","+(j)+"),("+i+","+j+")),\n"
+      cp += "less r"+(i+2)+"c"+(j+1)+" "+c+"\n"
     }
     if (Game.conditions[i][j].u) {
-      cp += "(("+(i-1)+","+(j)+"),("+i+","+j+")),\n"
+      cp += "less r"+(i)+"c"+(j+1)+" "+c+"\n"
     }
     if (Game.conditions[i][j].l) {
-      cp += "(("+(i)+","+(j-1)+"),("+i+","+j+")),\n"
+      cp += "less r"+(i+1)+"c"+(j)+" "+c+"\n"
     }
     if (Game.conditions[i][j].r) {
-      cp += "(("+(i)+","+(j+1)+"),("+i+","+j+")),\n"
+      cp += "less r"+(i+1)+"c"+(j+2)+" "+c+"\n"
     }
     if (Game.currentState.cellStatus[i][j].number != 0) {
-      var num = parseInt(Game.currentState.cellStatus[i][j].number,20)-1;
-      g += "("+i+","+j+","+num+"),\n"
+      var num = parseInt(Game.currentState.cellStatus[i][j].number,20);
+      g += "given "+c+" "+num+"\n"
     }
   }
 }
@@ -57,25 +66,17 @@ document.getElementById("robot").value = 1;
 */
 
 /* Normal sudokuwiki.org
-var gv = "";
+var gv = "size 9\ntype normal\ngivens\n";
 for (var i=0; i<g.cells.length; i++) {
-    if (g.cells[i].val != 0) {
-      var num = parseInt(g.cells[i].val,20)-1;
-      gv += "("+parseInt(i/9)+","+(i%9)+","+num+"),\n"
+    gv += g.cells[i].val != 0 ? g.cells[i].val : ".";
+    if (i%9 == 8) {
+      gv += "\n"
     }
 }
 console.log(gv);
  */
 
-#[derive(PartialEq)]
-pub enum GameType {
-    Normal,
-    Jigsaw,
-    Futoshiki,
-    Thermo,
-}
-
-fn get_hint_string<const SIZE: usize>(vec: &Vec<Tuple3D<SIZE>>) -> String{
+fn get_hint_string(vec: &Vec<Tuple3D>) -> String{
     let mut ret_str = String::new();
     for pos in vec {
         let row = char::from(65 + (pos.pos.0 as u8));
@@ -89,83 +90,281 @@ fn get_hint_string<const SIZE: usize>(vec: &Vec<Tuple3D<SIZE>>) -> String{
     }
 }
 
-fn main() {
-    let t = GameType::Futoshiki;
-    const size : usize = 5;
-
-    let mut cons: Vec<Box<dyn Constraint<_, SdkBoard<size>>>> = vec![
-        Box::new(RowUniqueConstraint),
-        Box::new(ColUniqueConstraint),
-        Box::new(DigitUniqueConstraint),
-        Box::new(RowExistConstraint),
-        Box::new(ColExistConstraint),
-        Box::new(DigitExistConstraint),
-    ];
-
-    if t == GameType::Normal {
-        for x in 0..3 {
-            for y in 0..3 {
-                cons.push(Box::new(CellConstraint {
-                    cells: vec![
-                        (3 * x, 3 * y),
-                        (3 * x, 3 * y + 1),
-                        (3 * x, 3 * y + 2),
-                        (3 * x + 1, 3 * y),
-                        (3 * x + 1, 3 * y + 1),
-                        (3 * x + 1, 3 * y + 2),
-                        (3 * x + 2, 3 * y),
-                        (3 * x + 2, 3 * y + 1),
-                        (3 * x + 2, 3 * y + 2),
-                    ],
-                }));
-            }
+/// Writes positions like "r1c1=5, r1c2=3" using the puzzle's digits
+fn positions_string(vec: &[Tuple3D], board: &SdkBoard) -> String {
+    let cells: Vec<String> = vec.iter()
+        .map(|v| format!("{}={}", cell_name(v.pos.0, v.pos.1), board.labels[v.pos.2]))
+        .collect();
+    cells.join(", ")
+}
+
+/// Describes a broken rule, writing digits the way the puzzle does
+fn contradiction_string(c: &Contradiction<Tuple3D>, board: &SdkBoard) -> String {
+    format!("Contradiction in {}: {}", c.rule, positions_string(&c.cells, board))
+}
+
+fn step_string(step: &Step, board: &SdkBoard) -> String {
+    let mut ret = format!("{} ({})", step.technique, step.detail);
+    if !step.placed.is_empty() {
+        ret += &format!("; placed {}", positions_string(&step.placed, board));
+    }
+    if !step.removed.is_empty() {
+        ret += &format!("; removed {}", positions_string(&step.removed, board));
+    }
+    ret
+}
+
+/// Reads a `--count` limit, which has to be at least 1 to find anything
+fn count_limit(arg: Option<String>) -> Option<usize> {
+    arg.and_then(|n| n.parse().ok()).filter(|n| *n >= 1)
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: true_logic_solver [--count <limit> | --steps | --rate | --generate [options]] <puzzle file>");
+    eprintln!("  --count <limit>  count solutions, stopping after <limit>, instead of solving");
+    eprintln!("  --steps          solve with human techniques, listing each step");
+    eprintln!("  --rate           score how hard the puzzle is for a person");
+    eprintln!("  --generate       make a new puzzle with the file's rules, keeping its givens");
+    eprintln!("    --seed <n>         make the same puzzle again");
+    eprintln!("    --symmetry <kind>  none, rotational, mirror or diagonal");
+    eprintln!("    --difficulty <n>   stop at this difficulty score, keeping the puzzle solvable by techniques");
+    process::exit(1);
+}
+
+/// Prints how many solutions a puzzle has, and what they are
+fn count(file: &PuzzleFile, limit: usize) {
+    let mut game = Puzzle::init(file.board());
+    game.constraints = file.constraints();
+
+    let solutions = game.count_solutions(limit);
+    println!("Solutions: {}", solutions.count);
+    for board in &solutions.examples {
+        println!();
+        println!("{}", board);
+    }
+}
+
+/// Prints each technique used, easiest first, as a person might solve it
+fn steps(file: &PuzzleFile) {
+    let mut game = Puzzle::init(file.board());
+    game.constraints = file.constraints();
+
+    let steps = match game.solve_steps() {
+        Ok(steps) => steps,
+        Err(c) => {
+            println!("{}", contradiction_string(&c, &game.board));
+            println!("{}", game.board);
+            return;
         }
-    } else if t == GameType::Jigsaw {
-        let cells = vec![];
-        for i in cells {
-            cons.push(Box::new(CellConstraint { cells: i }));
+    };
+    for (i, step) in steps.iter().enumerate() {
+        println!("{}. {}", i + 1, step_string(step, &game.board));
+    }
+    if game.board.num_solved() < game.board.max_solved() {
+        println!("Stuck with {} of {} cells filled", game.board.num_solved(), game.board.max_solved());
+    }
+    println!("{}", game.board);
+}
+
+fn print_rating(rating: &Rating) {
+    println!("Difficulty: {}", rating.score);
+    match rating.hardest {
+        Some(t) => println!("Hardest technique: {}", t),
+        None => println!("Hardest technique: none"),
+    }
+    if !rating.solved {
+        println!("Techniques got stuck, so this only rates the start");
+    }
+}
+
+/// Prints a difficulty score and the hardest technique needed
+fn rate(file: &PuzzleFile) {
+    let mut game = Puzzle::init(file.board());
+    game.constraints = file.constraints();
+
+    match game.rate() {
+        Ok(rating) => print_rating(&rating),
+        Err(c) => println!("{}", contradiction_string(&c, &game.board)),
+    }
+}
+
+/// Prints a new puzzle as `given` lines to add to the rules file, and its solution
+fn generate(file: &PuzzleFile, seed: u64, symmetry: Symmetry, target: Option<usize>) {
+    let rules = || file.constraints();
+    let mut generator = Generator::new(file.board(), &rules, seed);
+    generator.symmetry = symmetry;
+    generator.target = target;
+
+    let made = match generator.generate() {
+        Ok(Some(made)) => made,
+        Ok(None) => {
+            println!("No solution");
+            return;
         }
-    } else if t == GameType::Futoshiki || t == GameType::Thermo {
-        let pairs = vec![
-            ((0,2),(0,3)),
-((0,3),(0,4)),
-((0,1),(1,1)),
-((1,2),(1,3)),
-((0,4),(1,4)),
-((2,0),(2,1)),
-((1,3),(2,3)),
-((3,4),(2,4)),
-((4,1),(3,1)),
-((4,2),(3,2)),
-        ];
-        for (l, h) in pairs {
-            cons.push(Box::new(LessThanConstraint { lpos: l, hpos: h }));
+        Err(c) => {
+            println!("{}", contradiction_string(&c, &file.board()));
+            return;
         }
+    };
+    println!("Seed: {}", seed);
+    println!("Givens: {}", made.givens.len());
+    print_rating(&made.rating);
+    if made.target_met == Some(false) {
+        println!("Could not reach the difficulty target of {}", target.unwrap_or_default());
     }
-
-    let givens = vec![];
-    for i in givens {
-        cons.push(Box::new(GivenConstraint { pos: i }));
+    println!();
+    for (x, y, z) in &made.givens {
+        println!("given {} {}", cell_name(*x, *y), made.solution.labels[*z]);
     }
+    println!();
+    println!("{}", made.solution);
+}
 
-    let mut game = Puzzle::init(size);
-    game.constraints = cons;
+fn solve(file: &PuzzleFile) {
+    let mut game = Puzzle::init(file.board());
+    game.constraints = file.constraints();
 
-    println!("{}", get_hint_string(&vec![game.weak_hint().unwrap()]));
+    // Found before solving, but only shown once the puzzle turns out not to be broken
+    let hint = game.weak_hint();
 
     let mut tries = 0;
-    while game.solve_simple(false) {
-        tries += 1;
+    loop {
+        match game.solve(false) {
+            Ok(true) => tries += 1,
+            Ok(false) => break,
+            Err(c) => {
+                println!("{}", contradiction_string(&c, &game.board));
+                println!("{}", game.board);
+                return;
+            }
+        }
         //println!("{}", game.board);
         //println!("{:?}", game.board);
         //println!("{}", tries);
     }
+    if let Some(hint) = hint {
+        println!("{}", get_hint_string(&vec![hint]));
+    }
     println!("{}", get_hint_string(&game.strong_hint()));
 
     println!("Rounds of filling: {}", tries);
     println!("{}", game.board);
     println!("{:?}", game.board);
-    println!("document.getElementById(\"puzzleForm\").onsubmit = function() {{Game.saveState();Game.tickTimer();this.jstimerPersonal.value = Game.getTimer();this.ansH.value=\"{}\"}};\ndocument.getElementById(\"btnReady\").click();", game.board.serialize().unwrap());
 
+    if game.board.num_solved() < game.board.max_solved() {
+        println!("Logic got stuck with {} of {} cells filled, guessing the rest", game.board.num_solved(), game.board.max_solved());
+        let guessed = game.solve_backtrack();
+        println!("Guesses: {}", guessed.guesses);
+        if guessed.solution.is_none() {
+            println!("No solution");
+            return;
+        }
+        println!("{}", game.board);
+    }
+    if let Some(answer) = game.board.serialize() {
+        println!("document.getElementById(\"puzzleForm\").onsubmit = function() {{Game.saveState();Game.tickTimer();this.jstimerPersonal.value = Game.getTimer();this.ansH.value=\"{}\"}};\ndocument.getElementById(\"btnReady\").click();", answer);
+    }
+}
+
+/// What to do with the puzzle
+enum Mode {
+    Solve,
+    Count(usize),
+    Steps,
+    Rate,
+    Generate,
+}
+
+/// Reads the value after an option, or stops with the usage message
+fn option<I: Iterator<Item = String>, V: std::str::FromStr>(args: &mut I) -> V {
+    args.next().and_then(|v| v.parse().ok()).unwrap_or_else(|| usage())
+}
 
+fn main() {
+    let mut path = None;
+    let mut mode = Mode::Solve;
+    let mut seed = None;
+    let mut symmetry = Symmetry::None;
+    let mut target = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let next = match arg.as_str() {
+            "--count" => Mode::Count(count_limit(args.next()).unwrap_or_else(|| usage())),
+            "--steps" => Mode::Steps,
+            "--rate" => Mode::Rate,
+            "--generate" => Mode::Generate,
+            "--seed" => {
+                seed = Some(option(&mut args));
+                continue;
+            }
+            "--difficulty" => {
+                target = Some(option(&mut args));
+                continue;
+            }
+            "--symmetry" => {
+                symmetry = match option::<_, String>(&mut args).as_str() {
+                    "none" => Symmetry::None,
+                    "rotational" => Symmetry::Rotational,
+                    "mirror" => Symmetry::Mirror,
+                    "diagonal" => Symmetry::Diagonal,
+                    _ => usage(),
+                };
+                continue;
+            }
+            _ if path.is_none() => {
+                path = Some(arg);
+                continue;
+            }
+            _ => usage(),
+        };
+        if !matches!(mode, Mode::Solve) {
+            usage();
+        }
+        mode = next;
+    }
+    let path = path.unwrap_or_else(|| usage());
+    let src = match fs::read_to_string(&path) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Could not read {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    let file = match puzzle_file::parse(&src) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        }
+    };
+
+    match mode {
+        Mode::Solve => solve(&file),
+        Mode::Count(limit) => count(&file, limit),
+        Mode::Steps => steps(&file),
+        Mode::Rate => rate(&file),
+        Mode::Generate => {
+            if symmetry == Symmetry::Diagonal && file.rows != file.cols {
+                eprintln!("Diagonal symmetry needs a square grid");
+                process::exit(1);
+            }
+            let seed = seed.unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, |d| d.as_nanos() as u64));
+            generate(&file, seed, symmetry, target)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_limit_of_one_is_accepted() {
+        assert_eq!(count_limit(Some(String::from("1"))), Some(1));
+    }
+
+    #[test]
+    fn count_limit_of_zero_is_rejected() {
+        assert_eq!(count_limit(Some(String::from("0"))), None);
+    }
 }
@@ -0,0 +1,924 @@
+use std::fmt::{Display, Formatter};
+use crate::board::{SdkBoard, Tuple3D};
+use crate::constraints::{palindrome_constraint, thermo_constraint, whisper_constraint, ArrowConstraint, BetweenConstraint, CellConstraint, CellExistConstraint, ChessConstraint, Constraint, DigitSetConstraint, GivenConstraint, KillerCageConstraint, LittleKillerConstraint, PairConstraint, QuadrupleConstraint, RegionSumConstraint, Relation, RenbanConstraint, SandwichConstraint, SkyscraperConstraint, XSumConstraint};
+use crate::layout::{latin_square, neighbour_pairs, Adjacency, BoxLayout, Edge, Preset, Side};
+
+/* Puzzle file format
+Everything after a `#` is a comment. Cells are written as r<row>c<col>, counting from 1.
+
+size 9
+digits 1-9
+type normal
+boxes 3 3
+givens
+53..7....
+6..195...
+.98....6.
+8...6...3
+4..8.3..1
+7...2...6
+.6....28.
+...419..5
+....8..79
+region full r1c1 r1c2 r2c1 ...
+preset diagonals
+less r1c3 r1c4
+thermo r2c2 r2c3 r2c4
+cage 15 r1c1 r1c2 r1c3
+arrow r5c5 r4c4 r3c3
+arrow r7c1 r7c2 > r8c1 r9c1 r9c2
+white r1c1 r1c2
+black r2c1 r2c2
+negative kropki
+x r3c1 r3c2
+v r4c1 r4c2
+negative xv
+nonconsecutive
+german r5c1 r6c2 r7c3
+dutch r5c9 r6c8
+whisper 3 r9c5 r9c6 r9c7
+renban r6c6 r6c7 r7c7
+palindrome r8c1 r8c2 r8c3 r8c4
+between r1c9 r2c9 r3c9 r4c9
+regionsum r3c3 r3c4 r3c5
+sandwich top 2 15
+littlekiller top 3 right 20
+xsum left 4 25
+skyscraper right 5 3
+antiknight
+antiqueen 9
+antimove 1,2 2,1
+even r1c1 r2c2
+high r3c3
+only 1 5 9 r4c4
+quad r5c5 1 1 3
+given r9c9 4
+
+`size` is the number of rows then columns, or one number for a square grid.
+`digits` is a range like 0-8 or a list of labels like `1 2 3 4 5 6 7 8 9 A B C D E F G`, and has to
+come before anything that uses a digit. Without it the digits are 1 up to the longer side.
+Each row after `givens` is either one token with a character per cell, or one token per cell
+separated by spaces. `.` is an empty cell.
+`boxes` takes the width then the height of a box. A normal puzzle without it gets the most square
+boxes that fit, like 3x2 for a 6x6. Rows, columns and boxes with a cell for every digit must hold
+every digit; a `region` only has to when it starts with `full`.
+`even` and `odd` cells hold digits of that parity. `low` cells hold the smaller half of the digits
+and `high` cells the larger half, so 1-4 and 6-9 for 1-9. `only` lists digits then the cells that
+can only hold them.
+`quad` is a circle on the corner below and right of the cell, listing up to four digits that
+appear among the four cells around it. A digit listed twice appears twice.
+`preset` adds a common set of regions: `diagonals`, `windoku` (or `hyper`), `disjoint`, `argyle`,
+`asterisk` or `centredot`. They hold every digit when they have a cell for each one. `diagonals`
+and `argyle` need a square grid, `asterisk` a 9x9 one and `centredot` boxes of odd width and height.
+`cage` is a killer cage: its cells add up to the number and don't repeat a digit. Rules that add
+digits up need digits that are numbers.
+`arrow` starts with its circle, and the digits along the rest of it add up to the circle. For a pill
+of several cells, list them then `>` before the arrow, and the pill is read as one number.
+`white` and `black` are Kropki dots between two cells: a white dot's digits are 1 apart, and a black
+dot's are in ratio 1:2. `negative kropki` says every dot is given, so neighbouring cells without one
+are neither.
+`x` and `v` join two cells adding to 10 and 5, and `negative xv` says no other neighbours add to either.
+`nonconsecutive` stops neighbouring cells holding consecutive digits. It can be followed by
+`orthogonal`, `diagonal` or `king` for which cells are neighbours, orthogonal by default, and by
+the differences that are ruled out instead of 1, like `nonconsecutive king 1 2`.
+`german` and `dutch` whispers lines have neighbours at least 5 and 4 apart, and `whisper` takes the gap.
+`renban` cells hold consecutive digits in any order, without repeats.
+`palindrome` lines read the same from either end. A `between` line starts and ends with its circles,
+and the digits in between are strictly between theirs. A `regionsum` line is split where it crosses
+into another box or region, and every piece adds up to the same total.
+Clues outside the grid name a side, `top`, `bottom`, `left` or `right`, then the row or column
+they are beside. `sandwich` gives the sum of the digits between the smallest and largest digit.
+`littlekiller` gives the sum of the diagonal from the clue, heading `left` or `right` from a clue
+above or below the grid and `up` or `down` from one beside it. Digits on it may repeat.
+`xsum` gives the sum of the first X digits from the clue, where X is the first digit.
+`skyscraper` counts the digits seen from the clue, where larger digits hide the smaller ones behind
+them. `type skyscraper` is a Latin square without boxes, like futoshiki.
+`antiknight` and `antiking` stop a digit repeating a chess knight's or king's move away, and
+`antiqueen` does the same for a queen's moves, for just the digits listed after it. `antimove`
+takes any moves as row,column offsets, each going in every direction, so `antimove 1,2 2,1` is
+the same as `antiknight`.
+ */
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum GameType {
+    Normal,
+    Jigsaw,
+    Futoshiki,
+    Thermo,
+    Skyscraper,
+}
+
+/// A rule block from a puzzle file
+pub enum Rule {
+    /// No digit repeats in these cells, and if set every digit is there
+    Region(Vec<(usize, usize)>, bool),
+    /// The cells can only hold these digits, named like "even"
+    DigitSet(String, Vec<usize>, Vec<(usize, usize)>),
+    /// These digits appear among the four cells around the corner below and right of the cell
+    Quadruple((usize, usize), Vec<usize>),
+    /// A named set of extra regions
+    Preset(Preset),
+    /// The first cell is less than the second
+    LessThan((usize, usize), (usize, usize)),
+    /// A marker between two cells, like a Kropki dot
+    Pair((usize, usize), (usize, usize), Relation),
+    /// Neighbouring cells without a marker for one of these relations satisfy none of them
+    Negative(Vec<Relation>),
+    /// Neighbouring cells never differ by any of these
+    Apart(Adjacency, Vec<i64>),
+    /// Digits strictly increase from the bulb
+    Thermo(Vec<(usize, usize)>),
+    /// Neighbours along the line are at least this far apart
+    Whisper(i64, Vec<(usize, usize)>),
+    /// Consecutive digits in any order
+    Renban(Vec<(usize, usize)>),
+    /// Reads the same from either end
+    Palindrome(Vec<(usize, usize)>),
+    /// The digits strictly between the first and last cell's
+    Between(Vec<(usize, usize)>),
+    /// Each piece of the line within one region has the same sum
+    RegionSum(Vec<(usize, usize)>),
+    /// Digits between the smallest and largest digit of the row or column add up to the sum
+    Sandwich(Edge, i64),
+    /// The diagonal from a clue outside the grid adds up to the sum
+    LittleKiller(Vec<(usize, usize)>, i64),
+    /// The first X digits from the edge add up to the sum, where X is the first digit
+    XSum(Edge, i64),
+    /// This many digits can be seen from the edge, larger ones hiding smaller ones
+    Skyscraper(Edge, usize),
+    /// Cells a knight's move apart are different
+    AntiKnight,
+    /// Cells a king's move apart are different
+    AntiKing,
+    /// These digits never repeat a queen's move apart
+    AntiQueen(Vec<usize>),
+    /// Cells these (row, column) offsets apart, in any direction, are different
+    AntiMove(Vec<(isize, isize)>),
+    /// Killer cage: the cells add up to the sum without repeats
+    Cage(i64, Vec<(usize, usize)>),
+    /// The circle's cells read as a number equal the sum along the arrow
+    Arrow(Vec<(usize, usize)>, Vec<(usize, usize)>),
+}
+
+pub struct PuzzleFile {
+    pub rows: usize,
+    pub cols: usize,
+    /// How each digit is written
+    pub labels: Vec<String>,
+    /// Boxes to add, set for normal puzzles and whenever `boxes` is given
+    pub boxes: Option<BoxLayout>,
+    /// (row, col, digit), all starting at 0
+    pub givens: Vec<(usize, usize, usize)>,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub col: usize,
+    pub msg: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.col, self.msg)
+    }
+}
+
+/// A word of the file and where it starts, both counting from 1
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+    col: usize,
+}
+
+impl Token<'_> {
+    fn error(&self, msg: String) -> ParseError {
+        ParseError { line: self.line, col: self.col, msg }
+    }
+
+    fn number(&self) -> Result<usize, ParseError> {
+        self.text.parse()
+            .map_err(|_| self.error(format!("expected a number, found `{}`", self.text)))
+    }
+
+    /// Reads r<row>c<col> into a 0 based (row, col)
+    fn cell(&self, rows: usize, cols: usize) -> Result<(usize, usize), ParseError> {
+        let bad = || self.error(format!("expected a cell like r1c1, found `{}`", self.text));
+        let lower = self.text.to_ascii_lowercase();
+        let rest = lower.strip_prefix('r').ok_or_else(bad)?;
+        let (row, col) = rest.split_once('c').ok_or_else(bad)?;
+        let row: usize = row.parse().map_err(|_| bad())?;
+        let col: usize = col.parse().map_err(|_| bad())?;
+        if row == 0 || col == 0 || row > rows || col > cols {
+            return Err(self.error(format!("cell `{}` is outside the {}x{} grid", self.text, rows, cols)));
+        }
+        Ok((row - 1, col - 1))
+    }
+
+    /// Reads a digit label into its index
+    fn digit(&self, labels: &[String]) -> Result<usize, ParseError> {
+        labels.iter().position(|l| l == self.text)
+            .ok_or_else(|| self.error(format!("`{}` is not one of the digits {}", self.text, labels.join(" "))))
+    }
+}
+
+fn tokenize(line: &str, line_no: usize) -> Vec<Token<'_>> {
+    let line = match line.find('#') {
+        Some(i) => &line[..i],
+        None => line,
+    };
+    let mut ret = vec![];
+    let mut start = None;
+    for (i, c) in line.char_indices().chain([(line.len(), ' ')]) {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some(i),
+            (Some(s), true) => {
+                ret.push(Token { text: &line[s..i], line: line_no, col: line[..s].chars().count() + 1 });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    ret
+}
+
+/// Checks a directive has exactly `n` arguments after the keyword
+fn expect_args(tokens: &[Token], n: usize) -> Result<(), ParseError> {
+    if tokens.len() - 1 < n {
+        let last = tokens.last().unwrap();
+        return Err(ParseError {
+            line: last.line,
+            col: last.col + last.text.chars().count(),
+            msg: format!("`{}` needs {} argument(s)", tokens[0].text, n),
+        });
+    }
+    if tokens.len() - 1 > n {
+        return Err(tokens[n + 1].error(format!("unexpected `{}` after `{}`", tokens[n + 1].text, tokens[0].text)));
+    }
+    Ok(())
+}
+
+/// Reads the labels of a `digits` line, either a range like 1-9 or the labels themselves
+fn digit_labels(tokens: &[Token]) -> Result<Vec<String>, ParseError> {
+    if tokens.len() < 2 {
+        return Err(tokens[0].error(String::from("`digits` needs a range or a list of digits")));
+    }
+    if tokens.len() == 2 {
+        if let Some((lo, hi)) = tokens[1].text.split_once('-') {
+            let bad = || tokens[1].error(format!("expected a range like 1-9, found `{}`", tokens[1].text));
+            let lo: usize = lo.parse().map_err(|_| bad())?;
+            let hi: usize = hi.parse().map_err(|_| bad())?;
+            if hi < lo {
+                return Err(bad());
+            }
+            return Ok((lo..=hi).map(|d| d.to_string()).collect());
+        }
+    }
+    let mut labels: Vec<String> = vec![];
+    for t in &tokens[1..] {
+        if t.text == "." || labels.iter().any(|l| l == t.text) {
+            return Err(t.error(format!("`{}` can't be used as a digit", t.text)));
+        }
+        labels.push(t.text.to_string());
+    }
+    Ok(labels)
+}
+
+/// What the parser knows about the grid so far
+struct Parser {
+    rows: usize,
+    cols: usize,
+    /// Set by `digits`, or to the default once something needs a digit
+    labels: Option<Vec<String>>,
+}
+
+impl Parser {
+    fn labels(&mut self) -> &[String] {
+        let size = self.rows.max(self.cols);
+        self.labels.get_or_insert_with(|| (1..=size).map(|d| d.to_string()).collect())
+    }
+
+    /// Checks every digit is a number, for a rule that adds them up
+    fn numeric(&mut self, key: &Token) -> Result<(), ParseError> {
+        match self.labels().iter().find(|l| l.parse::<i64>().is_err()) {
+            Some(l) => Err(key.error(format!("`{}` adds up digits, but `{}` is not a number", key.text, l))),
+            None => Ok(()),
+        }
+    }
+
+    /// Reads a clue position outside the grid, like `top 3`
+    fn edge(&self, side: &Token, index: &Token) -> Result<Edge, ParseError> {
+        let side_ = match side.text {
+            "top" => Side::Top,
+            "bottom" => Side::Bottom,
+            "left" => Side::Left,
+            "right" => Side::Right,
+            t => return Err(side.error(format!("expected top, bottom, left or right, found `{}`", t))),
+        };
+        let len = if matches!(side_, Side::Top | Side::Bottom) { self.cols } else { self.rows };
+        let n = index.number()?;
+        if n == 0 || n > len {
+            return Err(index.error(format!("the grid has no {} {}", if matches!(side_, Side::Top | Side::Bottom) { "column" } else { "row" }, n)));
+        }
+        Ok(Edge { side: side_, index: n - 1 })
+    }
+
+    fn cells(&self, tokens: &[Token]) -> Result<Vec<(usize, usize)>, ParseError> {
+        tokens.iter().map(|t| t.cell(self.rows, self.cols)).collect()
+    }
+
+    /// Reads one row of the givens block
+    fn given_row(&mut self, tokens: &[Token], row: usize, givens: &mut Vec<(usize, usize, usize)>) -> Result<(), ParseError> {
+        let cols = self.cols;
+        let labels = self.labels();
+        let single_chars = labels.iter().all(|l| l.chars().count() == 1);
+        if tokens.len() == 1 && cols > 1 && single_chars {
+            let t = &tokens[0];
+            if t.text.chars().count() != cols {
+                return Err(t.error(format!("expected {} cells in row {}, found {}", cols, row + 1, t.text.chars().count())));
+            }
+            for (y, c) in t.text.chars().enumerate() {
+                if c == '.' {
+                    continue;
+                }
+                let z = labels.iter().position(|l| l.starts_with(c))
+                    .ok_or_else(|| ParseError { line: t.line, col: t.col + y, msg: format!("`{}` is not one of the digits {}", c, labels.join(" ")) })?;
+                givens.push((row, y, z));
+            }
+            return Ok(());
+        }
+        if tokens.len() != cols {
+            let t = tokens.get(cols).unwrap_or(&tokens[tokens.len() - 1]);
+            return Err(t.error(format!("expected {} cells in row {}, found {}", cols, row + 1, tokens.len())));
+        }
+        for (y, t) in tokens.iter().enumerate() {
+            if t.text != "." {
+                givens.push((row, y, t.digit(labels)?));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn parse(src: &str) -> Result<PuzzleFile, ParseError> {
+    let mut parser: Option<Parser> = None;
+    let mut game_type = GameType::Normal;
+    let mut type_pos = (1, 1);
+    let mut boxes = None;
+    let mut boxes_pos = (1, 1);
+    let mut givens = vec![];
+    let mut rules = vec![];
+    // Presets to check once the boxes are known, with where they were named
+    let mut presets = vec![];
+    // Rows of the givens block still to read, and the line that started it
+    let mut given_rows = 0..0;
+    let mut givens_line = 0;
+
+    for (i, line) in src.lines().enumerate() {
+        let tokens = tokenize(line, i + 1);
+        if tokens.is_empty() {
+            continue;
+        }
+        if let Some(row) = given_rows.next() {
+            parser.as_mut().unwrap().given_row(&tokens, row, &mut givens)?;
+            continue;
+        }
+        let key = &tokens[0];
+        if key.text == "size" {
+            if parser.is_some() {
+                return Err(key.error(String::from("size is given twice")));
+            }
+            if tokens.len() == 1 {
+                return Err(ParseError { line: key.line, col: key.col + key.text.chars().count(), msg: String::from("`size` needs 1 or 2 arguments") });
+            }
+            if tokens.len() > 3 {
+                expect_args(&tokens, 2)?;
+            }
+            let rows = tokens[1].number()?;
+            let cols = match tokens.get(2) {
+                Some(t) => t.number()?,
+                None => rows,
+            };
+            if rows == 0 || cols == 0 {
+                return Err(tokens[1].error(String::from("size must be at least 1")));
+            }
+            parser = Some(Parser { rows, cols, labels: None });
+            continue;
+        }
+        let p = parser.as_mut().ok_or_else(|| key.error(format!("`{}` before `size`", key.text)))?;
+        match key.text {
+            "digits" => {
+                if p.labels.is_some() {
+                    return Err(key.error(String::from("`digits` has to come before anything that uses a digit")));
+                }
+                let labels = digit_labels(&tokens)?;
+                if labels.len() < p.rows.max(p.cols) {
+                    return Err(tokens[1].error(format!("{} digits can't fill a {}x{} grid without repeats", labels.len(), p.rows, p.cols)));
+                }
+                p.labels = Some(labels);
+            }
+            "type" => {
+                expect_args(&tokens, 1)?;
+                game_type = match tokens[1].text {
+                    "normal" => GameType::Normal,
+                    "jigsaw" => GameType::Jigsaw,
+                    "futoshiki" => GameType::Futoshiki,
+                    "thermo" => GameType::Thermo,
+                    "skyscraper" => GameType::Skyscraper,
+                    t => return Err(tokens[1].error(format!("unknown puzzle type `{}`", t))),
+                };
+                type_pos = (tokens[1].line, tokens[1].col);
+            }
+            "boxes" => {
+                expect_args(&tokens, 2)?;
+                let layout = BoxLayout::new(p.rows, p.cols, tokens[1].number()?, tokens[2].number()?)
+                    .map_err(|e| tokens[1].error(e.to_string()))?;
+                boxes = Some(layout);
+                boxes_pos = (tokens[1].line, tokens[1].col);
+            }
+            "givens" => {
+                expect_args(&tokens, 0)?;
+                given_rows = 0..p.rows;
+                givens_line = key.line;
+            }
+            "given" => {
+                expect_args(&tokens, 2)?;
+                let (x, y) = tokens[1].cell(p.rows, p.cols)?;
+                givens.push((x, y, tokens[2].digit(p.labels())?));
+            }
+            "region" => {
+                let full = tokens.get(1).map(|t| t.text) == Some("full");
+                let start = if full { 2 } else { 1 };
+                let cells = p.cells(&tokens[start..])?;
+                let digits = p.labels().len();
+                if cells.len() > digits {
+                    return Err(key.error(format!("a region of {} cells can't avoid repeating {} digits", cells.len(), digits)));
+                }
+                if full && cells.len() < digits {
+                    return Err(tokens[1].error(format!("a full region needs {} cells, found {}", digits, cells.len())));
+                }
+                rules.push(Rule::Region(cells, full));
+            }
+            "even" | "odd" | "low" | "high" => {
+                if matches!(key.text, "even" | "odd") {
+                    p.numeric(key)?;
+                }
+                let cells = p.cells(&tokens[1..])?;
+                if cells.is_empty() {
+                    return Err(key.error(format!("`{}` needs at least one cell", key.text)));
+                }
+                let labels = p.labels();
+                let half = labels.len() / 2;
+                let digits = (0..labels.len()).filter(|z| match key.text {
+                    "even" => labels[*z].parse::<i64>().is_ok_and(|v| v % 2 == 0),
+                    "odd" => labels[*z].parse::<i64>().is_ok_and(|v| v % 2 != 0),
+                    "low" => *z < half,
+                    _ => *z >= labels.len() - half,
+                }).collect();
+                rules.push(Rule::DigitSet(key.text.to_string(), digits, cells));
+            }
+            "only" => {
+                let labels = p.labels();
+                let count = tokens[1..].iter().take_while(|t| labels.iter().any(|l| l == t.text)).count();
+                let digits = tokens[1..=count].iter().map(|t| t.digit(labels)).collect::<Result<_, _>>()?;
+                let cells = p.cells(&tokens[count + 1..])?;
+                if count == 0 || cells.is_empty() {
+                    return Err(key.error(String::from("`only` needs the digits then at least one cell")));
+                }
+                rules.push(Rule::DigitSet(String::from("restricted"), digits, cells));
+            }
+            "quad" => {
+                if tokens.len() < 3 || tokens.len() > 6 {
+                    return Err(key.error(String::from("a quadruple needs a cell then one to four digits")));
+                }
+                let (x, y) = tokens[1].cell(p.rows, p.cols)?;
+                if x + 1 >= p.rows || y + 1 >= p.cols {
+                    return Err(tokens[1].error(format!("`{}` has no corner below and right of it in the grid", tokens[1].text)));
+                }
+                let labels = p.labels();
+                let digits = tokens[2..].iter().map(|t| t.digit(labels)).collect::<Result<_, _>>()?;
+                rules.push(Rule::Quadruple((x, y), digits));
+            }
+            "preset" => {
+                expect_args(&tokens, 1)?;
+                let preset = match tokens[1].text {
+                    "diagonals" => Preset::Diagonals,
+                    "windoku" | "hyper" => Preset::Windoku,
+                    "disjoint" => Preset::DisjointGroups,
+                    "argyle" => Preset::Argyle,
+                    "asterisk" => Preset::Asterisk,
+                    "centredot" | "centerdot" => Preset::CentreDot,
+                    t => return Err(tokens[1].error(format!("unknown preset `{}`", t))),
+                };
+                presets.push((preset, tokens[1].line, tokens[1].col));
+                rules.push(Rule::Preset(preset));
+            }
+            "less" => {
+                expect_args(&tokens, 2)?;
+                rules.push(Rule::LessThan(tokens[1].cell(p.rows, p.cols)?, tokens[2].cell(p.rows, p.cols)?));
+            }
+            "nonconsecutive" => {
+                p.numeric(key)?;
+                let (adjacency, start) = match tokens.get(1).map(|t| t.text) {
+                    Some("orthogonal") => (Adjacency::Orthogonal, 2),
+                    Some("diagonal") => (Adjacency::Diagonal, 2),
+                    Some("king") => (Adjacency::King, 2),
+                    _ => (Adjacency::Orthogonal, 1),
+                };
+                let mut differences = vec![];
+                for t in &tokens[start..] {
+                    differences.push(t.number()? as i64);
+                }
+                if differences.is_empty() {
+                    differences.push(1);
+                }
+                rules.push(Rule::Apart(adjacency, differences));
+            }
+            "thermo" => {
+                if tokens.len() < 3 {
+                    return Err(key.error(String::from("a thermo needs at least two cells")));
+                }
+                rules.push(Rule::Thermo(p.cells(&tokens[1..])?));
+            }
+            "german" | "dutch" | "whisper" => {
+                p.numeric(key)?;
+                let (gap, start) = match key.text {
+                    "german" => (5, 1),
+                    "dutch" => (4, 1),
+                    _ => (tokens.get(1).ok_or_else(|| key.error(String::from("`whisper` needs a gap")))?.number()? as i64, 2),
+                };
+                if tokens.len() < start + 2 {
+                    return Err(key.error(String::from("a whispers line needs at least two cells")));
+                }
+                rules.push(Rule::Whisper(gap, p.cells(&tokens[start..])?));
+            }
+            "renban" => {
+                p.numeric(key)?;
+                let cells = p.cells(&tokens[1..])?;
+                if cells.is_empty() || cells.len() > p.labels().len() {
+                    return Err(key.error(format!("a renban needs between 1 and {} cells", p.labels().len())));
+                }
+                rules.push(Rule::Renban(cells));
+            }
+            "palindrome" => {
+                if tokens.len() < 3 {
+                    return Err(key.error(String::from("a palindrome needs at least two cells")));
+                }
+                rules.push(Rule::Palindrome(p.cells(&tokens[1..])?));
+            }
+            "between" => {
+                p.numeric(key)?;
+                if tokens.len() < 4 {
+                    return Err(key.error(String::from("a between line needs two circles and a cell between them")));
+                }
+                rules.push(Rule::Between(p.cells(&tokens[1..])?));
+            }
+            "regionsum" => {
+                p.numeric(key)?;
+                if tokens.len() < 3 {
+                    return Err(key.error(String::from("a region sum line needs at least two cells")));
+                }
+                rules.push(Rule::RegionSum(p.cells(&tokens[1..])?));
+            }
+            "sandwich" => {
+                expect_args(&tokens, 3)?;
+                p.numeric(key)?;
+                rules.push(Rule::Sandwich(p.edge(&tokens[1], &tokens[2])?, tokens[3].number()? as i64));
+            }
+            "littlekiller" => {
+                expect_args(&tokens, 4)?;
+                p.numeric(key)?;
+                let edge = p.edge(&tokens[1], &tokens[2])?;
+                let towards = match (edge.side, tokens[3].text) {
+                    (Side::Top | Side::Bottom, "left") => Side::Left,
+                    (Side::Top | Side::Bottom, "right") => Side::Right,
+                    (Side::Left | Side::Right, "up") => Side::Top,
+                    (Side::Left | Side::Right, "down") => Side::Bottom,
+                    (Side::Top | Side::Bottom, t) => return Err(tokens[3].error(format!("expected left or right, found `{}`", t))),
+                    (Side::Left | Side::Right, t) => return Err(tokens[3].error(format!("expected up or down, found `{}`", t))),
+                };
+                rules.push(Rule::LittleKiller(edge.diagonal(p.rows, p.cols, towards), tokens[4].number()? as i64));
+            }
+            "xsum" => {
+                expect_args(&tokens, 3)?;
+                p.numeric(key)?;
+                rules.push(Rule::XSum(p.edge(&tokens[1], &tokens[2])?, tokens[3].number()? as i64));
+            }
+            "skyscraper" => {
+                expect_args(&tokens, 3)?;
+                if p.labels().len() > usize::BITS as usize {
+                    return Err(key.error(format!("skyscraper clues work with at most {} digits", usize::BITS)));
+                }
+                let edge = p.edge(&tokens[1], &tokens[2])?;
+                let count = tokens[3].number()?;
+                let len = edge.cells(p.rows, p.cols).len();
+                if count == 0 || count > len {
+                    return Err(tokens[3].error(format!("a skyscraper clue {} can see between 1 and {} digits", edge, len)));
+                }
+                rules.push(Rule::Skyscraper(edge, count));
+            }
+            "antiknight" | "antiking" => {
+                expect_args(&tokens, 0)?;
+                rules.push(if key.text == "antiknight" { Rule::AntiKnight } else { Rule::AntiKing });
+            }
+            "antiqueen" => {
+                if tokens.len() < 2 {
+                    return Err(key.error(String::from("`antiqueen` needs the digits it applies to")));
+                }
+                let labels = p.labels();
+                let digits = tokens[1..].iter().map(|t| t.digit(labels)).collect::<Result<_, _>>()?;
+                rules.push(Rule::AntiQueen(digits));
+            }
+            "antimove" => {
+                if tokens.len() < 2 {
+                    return Err(key.error(String::from("`antimove` needs at least one offset like 1,2")));
+                }
+                let mut offsets = vec![];
+                for t in &tokens[1..] {
+                    let offset = t.text.split_once(',')
+                        .and_then(|(dx, dy)| Some((dx.parse::<isize>().ok()?, dy.parse::<isize>().ok()?)))
+                        .filter(|o| *o != (0, 0))
+                        .ok_or_else(|| t.error(format!("expected an offset like 1,2, found `{}`", t.text)))?;
+                    offsets.push(offset);
+                }
+                rules.push(Rule::AntiMove(offsets));
+            }
+            "cage" => {
+                if tokens.len() < 3 {
+                    return Err(key.error(String::from("a cage needs a sum and at least one cell")));
+                }
+                p.numeric(key)?;
+                let sum = tokens[1].number()? as i64;
+                let cells = p.cells(&tokens[2..])?;
+                if cells.len() > p.labels().len() {
+                    return Err(key.error(format!("a cage of {} cells can't avoid repeating {} digits", cells.len(), p.labels().len())));
+                }
+                rules.push(Rule::Cage(sum, cells));
+            }
+            "arrow" => {
+                p.numeric(key)?;
+                let (circle, arrow) = match tokens.iter().position(|t| t.text == ">") {
+                    Some(i) => (&tokens[1..i], &tokens[i + 1..]),
+                    None => tokens[1..].split_at(1.min(tokens.len() - 1)),
+                };
+                let (circle, arrow) = (p.cells(circle)?, p.cells(arrow)?);
+                if circle.is_empty() || arrow.is_empty() {
+                    return Err(key.error(String::from("an arrow needs a circle and at least one cell along it")));
+                }
+                rules.push(Rule::Arrow(circle, arrow));
+            }
+            "white" | "black" => {
+                expect_args(&tokens, 2)?;
+                p.numeric(key)?;
+                let relation = if key.text == "white" { Relation::Difference(1) } else { Relation::Ratio(2) };
+                rules.push(Rule::Pair(tokens[1].cell(p.rows, p.cols)?, tokens[2].cell(p.rows, p.cols)?, relation));
+            }
+            "x" | "v" => {
+                expect_args(&tokens, 2)?;
+                p.numeric(key)?;
+                let relation = if key.text == "x" { Relation::Sum(10) } else { Relation::Sum(5) };
+                rules.push(Rule::Pair(tokens[1].cell(p.rows, p.cols)?, tokens[2].cell(p.rows, p.cols)?, relation));
+            }
+            "negative" => {
+                expect_args(&tokens, 1)?;
+                p.numeric(key)?;
+                let relations = match tokens[1].text {
+                    "kropki" => vec![Relation::Difference(1), Relation::Ratio(2)],
+                    "xv" => vec![Relation::Sum(10), Relation::Sum(5)],
+                    t => return Err(tokens[1].error(format!("unknown markers `{}`", t))),
+                };
+                rules.push(Rule::Negative(relations));
+            }
+            t => return Err(key.error(format!("unknown rule `{}`", t))),
+        }
+    }
+
+    if !given_rows.is_empty() {
+        return Err(ParseError { line: givens_line, col: 1, msg: format!("givens block is missing {} row(s)", given_rows.len()) });
+    }
+    let mut p = parser.ok_or(ParseError { line: 1, col: 1, msg: String::from("no `size` in puzzle") })?;
+    let labels = p.labels().to_vec();
+    if game_type == GameType::Normal && boxes.is_none() {
+        if p.rows != p.cols {
+            return Err(ParseError { line: type_pos.0, col: type_pos.1, msg: String::from("a normal puzzle that isn't square needs `boxes`") });
+        }
+        let layout = BoxLayout::standard(p.rows)
+            .map_err(|e| ParseError { line: type_pos.0, col: type_pos.1, msg: e.to_string() })?;
+        boxes = Some(layout);
+        boxes_pos = type_pos;
+    }
+    if let Some(layout) = &boxes {
+        layout.check_digits(labels.len())
+            .map_err(|e| ParseError { line: boxes_pos.0, col: boxes_pos.1, msg: e.to_string() })?;
+    }
+    for (preset, line, col) in presets {
+        preset.regions(p.rows, p.cols, boxes.as_ref())
+            .map_err(|e| ParseError { line, col, msg: e.to_string() })?;
+    }
+    Ok(PuzzleFile { rows: p.rows, cols: p.cols, labels, boxes, givens, rules })
+}
+
+impl PuzzleFile {
+    /// An empty board the size of the puzzle
+    pub fn board(&self) -> SdkBoard {
+        SdkBoard::new(self.rows, self.cols, self.labels.clone())
+    }
+
+    /// Orthogonal neighbours without a marker for any of the relations
+    fn unmarked(&self, relations: &[Relation]) -> Vec<((usize, usize), (usize, usize))> {
+        let marked = |a: (usize, usize), b: (usize, usize)| self.rules.iter().any(|r| match r {
+            Rule::Pair(a_, b_, rel) => relations.contains(rel) && ((a, b) == (*a_, *b_) || (a, b) == (*b_, *a_)),
+            _ => false,
+        });
+        neighbour_pairs(self.rows, self.cols, Adjacency::Orthogonal).into_iter()
+            .filter(|(a, b)| !marked(*a, *b))
+            .collect()
+    }
+
+    /// The boxes and `region` cells, which region sum lines are split by
+    fn regions(&self) -> Vec<Vec<(usize, usize)>> {
+        let mut ret = self.boxes.as_ref().map_or(vec![], |b| b.regions());
+        for rule in &self.rules {
+            if let Rule::Region(cells, _) = rule {
+                ret.push(cells.clone());
+            }
+        }
+        ret
+    }
+
+    /// Splits a line into runs of cells in the same region. A cell outside every region
+    /// starts a piece of its own
+    fn segments(&self, cells: &[(usize, usize)]) -> Vec<Vec<(usize, usize)>> {
+        let regions = self.regions();
+        let region = |c: &(usize, usize)| regions.iter().position(|r| r.contains(c));
+        let mut ret: Vec<Vec<(usize, usize)>> = vec![];
+        for (i, c) in cells.iter().enumerate() {
+            match ret.last_mut() {
+                Some(seg) if region(c).is_some() && region(c) == region(&cells[i - 1]) => seg.push(*c),
+                _ => ret.push(vec![*c]),
+            }
+        }
+        ret
+    }
+
+    /// Builds the constraints the puzzle describes
+    pub(crate) fn constraints(&self) -> Vec<Box<dyn Constraint<Tuple3D, SdkBoard>>> {
+        let digits = self.labels.len();
+        let mut cons = match &self.boxes {
+            Some(layout) => layout.constraints(digits),
+            None => latin_square(self.rows, self.cols, digits),
+        };
+
+        for rule in &self.rules {
+            match rule {
+                Rule::Region(cells, full) => {
+                    if *full {
+                        cons.push(Box::new(CellExistConstraint { cells: cells.clone() }));
+                    }
+                    cons.push(Box::new(CellConstraint { cells: cells.clone() }));
+                }
+                Rule::DigitSet(name, digits, cells) => {
+                    for cell in cells {
+                        cons.push(Box::new(DigitSetConstraint { name: name.clone(), cell: *cell, digits: digits.clone() }));
+                    }
+                }
+                Rule::Quadruple((x, y), digits) => cons.push(Box::new(QuadrupleConstraint {
+                    cells: vec![(*x, *y), (*x, y + 1), (x + 1, *y), (x + 1, y + 1)],
+                    digits: digits.clone(),
+                })),
+                Rule::Preset(preset) => {
+                    for cells in preset.regions(self.rows, self.cols, self.boxes.as_ref()).unwrap_or_default() {
+                        if cells.len() == digits {
+                            cons.push(Box::new(CellExistConstraint { cells: cells.clone() }));
+                        }
+                        cons.push(Box::new(CellConstraint { cells }));
+                    }
+                }
+                Rule::LessThan(l, h) => cons.push(Box::new(PairConstraint::less(*l, *h))),
+                Rule::Pair(a, b, relation) => cons.push(Box::new(PairConstraint { a: *a, b: *b, relations: vec![*relation], negated: false })),
+                Rule::Negative(relations) => {
+                    for (a, b) in self.unmarked(relations) {
+                        cons.push(Box::new(PairConstraint { a, b, relations: relations.clone(), negated: true }));
+                    }
+                }
+                Rule::Apart(adjacency, differences) => {
+                    let relations: Vec<Relation> = differences.iter().map(|d| Relation::Difference(*d)).collect();
+                    for (a, b) in neighbour_pairs(self.rows, self.cols, *adjacency) {
+                        cons.push(Box::new(PairConstraint { a, b, relations: relations.clone(), negated: true }));
+                    }
+                }
+                Rule::Thermo(cells) => {
+                    for c in thermo_constraint(cells.clone()) {
+                        cons.push(Box::new(c));
+                    }
+                }
+                Rule::Whisper(gap, cells) => {
+                    for c in whisper_constraint(cells.clone(), *gap) {
+                        cons.push(Box::new(c));
+                    }
+                }
+                Rule::Renban(cells) => cons.push(Box::new(RenbanConstraint { cells: cells.clone() })),
+                Rule::Palindrome(cells) => {
+                    for c in palindrome_constraint(cells.clone()) {
+                        cons.push(Box::new(c));
+                    }
+                }
+                Rule::Between(cells) => cons.push(Box::new(BetweenConstraint {
+                    ends: (cells[0], cells[cells.len() - 1]),
+                    cells: cells[1..cells.len() - 1].to_vec(),
+                })),
+                Rule::RegionSum(cells) => cons.push(Box::new(RegionSumConstraint { segments: self.segments(cells) })),
+                Rule::Sandwich(edge, sum) => cons.push(Box::new(SandwichConstraint { edge: *edge, sum: *sum })),
+                Rule::LittleKiller(cells, sum) => cons.push(Box::new(LittleKillerConstraint { cells: cells.clone(), sum: *sum })),
+                Rule::XSum(edge, sum) => cons.push(Box::new(XSumConstraint { edge: *edge, sum: *sum })),
+                Rule::Skyscraper(edge, count) => cons.push(Box::new(SkyscraperConstraint { edge: *edge, count: *count })),
+                Rule::AntiKnight => cons.push(Box::new(ChessConstraint::knight())),
+                Rule::AntiKing => cons.push(Box::new(ChessConstraint::king())),
+                Rule::AntiQueen(digits) => cons.push(Box::new(ChessConstraint::queen(digits.clone()))),
+                Rule::AntiMove(offsets) => cons.push(Box::new(ChessConstraint::offsets(offsets))),
+                Rule::Cage(sum, cells) => cons.push(Box::new(KillerCageConstraint { cells: cells.clone(), sum: *sum })),
+                Rule::Arrow(circle, arrow) => cons.push(Box::new(ArrowConstraint { circle: circle.clone(), arrow: arrow.clone() })),
+            }
+        }
+
+        for pos in &self.givens {
+            cons.push(Box::new(GivenConstraint { pos: *pos }));
+        }
+        cons
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(src: &str) -> (usize, usize, String) {
+        match parse(src) {
+            Ok(_) => panic!("expected a parse error"),
+            Err(e) => (e.line, e.col, e.msg),
+        }
+    }
+
+    #[test]
+    fn unknown_keyword_is_reported_where_it_starts() {
+        let (line, col, msg) = error("size 4\n\n  blah r1c1\n");
+        assert_eq!((line, col), (3, 3));
+        assert_eq!(msg, "unknown rule `blah`");
+    }
+
+    #[test]
+    fn bad_cell_is_reported_where_it_starts() {
+        let (line, col, msg) = error("size 4\nless r1c1 r1x2\n");
+        assert_eq!((line, col), (2, 11));
+        assert_eq!(msg, "expected a cell like r1c1, found `r1x2`");
+    }
+
+    #[test]
+    fn missing_argument_is_reported_after_the_line() {
+        let (line, col, msg) = error("size 4\nless r1c1\n");
+        assert_eq!((line, col), (2, 10));
+        assert_eq!(msg, "`less` needs 2 argument(s)");
+    }
+
+    #[test]
+    fn extra_argument_is_reported_where_it_starts() {
+        let (line, col, _) = error("size 4\ngiven r1c1 2 3\n");
+        assert_eq!((line, col), (2, 14));
+    }
+
+    #[test]
+    fn bare_size_allows_one_or_two_arguments() {
+        let (line, col, msg) = error("size\n");
+        assert_eq!((line, col), (1, 5));
+        assert_eq!(msg, "`size` needs 1 or 2 arguments");
+        assert!(parse("size 4\n").is_ok());
+        assert!(parse("size 4 6\ntype futoshiki\n").is_ok());
+    }
+
+    #[test]
+    fn skyscrapers_need_digits_that_fit_a_mask() {
+        let n = usize::BITS as usize + 1;
+        let digits: Vec<String> = (1..=n).map(|d| d.to_string()).collect();
+        let src = format!("size {}\ndigits {}\nskyscraper left 1 3\n", n, digits.join(" "));
+        let (line, col, msg) = error(&src);
+        assert_eq!((line, col), (3, 1));
+        assert_eq!(msg, format!("skyscraper clues work with at most {} digits", usize::BITS));
+    }
+
+    #[test]
+    fn antimove_reads_offsets() {
+        let file = parse("size 4\nantimove 1,2 0,-1\n").unwrap();
+        assert!(matches!(&file.rules[..], [Rule::AntiMove(o)] if *o == vec![(1, 2), (0, -1)]));
+        let (line, col, _) = error("size 4\nantimove 1,2 0,0\n");
+        assert_eq!((line, col), (2, 14));
+    }
+
+    #[test]
+    fn nonconsecutive_takes_neighbours_and_differences() {
+        let file = parse("size 4\nnonconsecutive king 1 2\n").unwrap();
+        assert!(matches!(&file.rules[..], [Rule::Apart(Adjacency::King, d)] if *d == vec![1, 2]));
+        let file = parse("size 4\nnonconsecutive\n").unwrap();
+        assert!(matches!(&file.rules[..], [Rule::Apart(Adjacency::Orthogonal, d)] if *d == vec![1]));
+    }
+}
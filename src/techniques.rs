@@ -0,0 +1,603 @@
+use std::fmt::{Display, Formatter};
+use crate::board::LogicVal::{False, Poss, True};
+use crate::board::{Board, Grid, Puzzle, Tuple3D};
+use crate::constraints::{cell_name, region_name, Contradiction, House};
+
+/// Human solving techniques, from easiest to hardest
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Technique {
+    /// One of the puzzle's own rules, like a given or a thermo, applied directly
+    Rule,
+    NakedSingle,
+    HiddenSingle,
+    LockedCandidates,
+    NakedPair,
+    HiddenPair,
+    NakedTriple,
+    HiddenTriple,
+    NakedQuad,
+    HiddenQuad,
+    XWing,
+    Swordfish,
+    XYWing,
+    /// Alternating inference chains of weak and strong links. Strong links only come from the
+    /// rows, columns, regions and cells that must hold a digit, so other rules only add weak ones
+    Chain,
+}
+
+impl Technique {
+    pub const ALL: [Technique; 14] = [
+        Technique::Rule,
+        Technique::NakedSingle,
+        Technique::HiddenSingle,
+        Technique::LockedCandidates,
+        Technique::NakedPair,
+        Technique::HiddenPair,
+        Technique::NakedTriple,
+        Technique::HiddenTriple,
+        Technique::NakedQuad,
+        Technique::HiddenQuad,
+        Technique::XWing,
+        Technique::Swordfish,
+        Technique::XYWing,
+        Technique::Chain,
+    ];
+
+    /// Points for each use when rating a puzzle, so many easy steps can
+    /// still add up to a harder puzzle
+    pub fn weight(&self) -> usize {
+        match self {
+            Technique::Rule => 1,
+            Technique::NakedSingle => 1,
+            Technique::HiddenSingle => 2,
+            Technique::LockedCandidates => 5,
+            Technique::NakedPair => 10,
+            Technique::HiddenPair => 12,
+            Technique::NakedTriple => 15,
+            Technique::HiddenTriple => 18,
+            Technique::NakedQuad => 25,
+            Technique::HiddenQuad => 28,
+            Technique::XWing => 30,
+            Technique::Swordfish => 40,
+            Technique::XYWing => 45,
+            Technique::Chain => 60,
+        }
+    }
+}
+
+impl Display for Technique {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Technique::Rule => "Puzzle rules",
+            Technique::NakedSingle => "Naked single",
+            Technique::HiddenSingle => "Hidden single",
+            Technique::LockedCandidates => "Locked candidates",
+            Technique::NakedPair => "Naked pair",
+            Technique::HiddenPair => "Hidden pair",
+            Technique::NakedTriple => "Naked triple",
+            Technique::HiddenTriple => "Hidden triple",
+            Technique::NakedQuad => "Naked quad",
+            Technique::HiddenQuad => "Hidden quad",
+            Technique::XWing => "X-Wing",
+            Technique::Swordfish => "Swordfish",
+            Technique::XYWing => "XY-Wing",
+            Technique::Chain => "Chain",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// One step of a solve: the technique used, where, and what it changed.
+/// Eliminations that follow from the digits it placed are included
+pub struct Step {
+    pub technique: Technique,
+    /// Where the technique was found, like "r1c1, r1c5 in row 1"
+    pub detail: String,
+    pub placed: Vec<Tuple3D>,
+    pub removed: Vec<Tuple3D>,
+    /// The technique's weight, plus more for longer chains
+    pub difficulty: usize,
+}
+
+/// How hard a puzzle is for a person
+pub struct Rating {
+    /// Sum of the difficulty of every step
+    pub score: usize,
+    /// None if the puzzle needed no steps at all
+    pub hardest: Option<Technique>,
+    /// Whether the techniques finished the puzzle with its solution. If not, the score only
+    /// covers the start
+    pub solved: bool,
+}
+
+/// Cells in which no digit repeats, and if `full` every digit appears
+struct Region {
+    cells: Vec<(usize, usize)>,
+    full: bool,
+}
+
+/// Every way to pick `n` of `items`, keeping their order
+fn combinations<I: Copy>(items: &[I], n: usize) -> Vec<Vec<I>> {
+    if n == 0 {
+        return vec![vec![]];
+    }
+    let mut ret = vec![];
+    for i in 0..items.len() {
+        for mut rest in combinations(&items[i + 1..], n - 1) {
+            rest.insert(0, items[i]);
+            ret.push(rest);
+        }
+    }
+    ret
+}
+
+fn cells_name(cells: &[(usize, usize)]) -> String {
+    cells.iter().map(|(x, y)| cell_name(*x, *y)).collect::<Vec<_>>().join(", ")
+}
+
+impl<S: Board<Tuple3D> + Grid> Puzzle<Tuple3D, S> {
+    /// Solves using the easiest technique that works at each step, starting over from
+    /// the easiest after every step. Stops when solved or when nothing works
+    pub(crate) fn solve_steps(&mut self) -> Result<Vec<Step>, Contradiction<Tuple3D>> {
+        let regions = self.regions();
+        let mut steps = vec![];
+        self.check()?;
+        while self.board.num_solved() < self.board.max_solved() {
+            match self.next_step(&regions)? {
+                Some(step) => steps.push(step),
+                None => break,
+            }
+        }
+        Ok(steps)
+    }
+
+    /// Rates a puzzle by solving it with the least powerful technique that works at each step.
+    /// The puzzle only counts as solved when the techniques reach the same digits as a search
+    pub(crate) fn rate(&mut self) -> Result<Rating, Contradiction<Tuple3D>> {
+        let solution = self.count_solutions(1).examples.pop();
+        let steps = self.solve_steps()?;
+        let finished = self.board.num_solved() == self.board.max_solved();
+        Ok(Rating {
+            score: steps.iter().map(|s| s.difficulty).sum(),
+            hardest: steps.iter().map(|s| s.technique).max(),
+            solved: finished && solution.is_some_and(|s| self.matches(&s)),
+        })
+    }
+
+    /// Whether every digit placed so far is in `solution`
+    pub(crate) fn matches(&self, solution: &S) -> bool {
+        self.board.positions().iter().all(|v| self.board.get(v) != True || solution.get(v) == True)
+    }
+
+    /// Applies the easiest technique that makes progress
+    fn next_step(&mut self, regions: &[Region]) -> Result<Option<Step>, Contradiction<Tuple3D>> {
+        for technique in Technique::ALL {
+            let before = self.board.clone();
+            let mut difficulty = technique.weight();
+            let detail = match technique {
+                Technique::Rule => self.rules()?,
+                Technique::NakedSingle => self.naked_single(),
+                Technique::HiddenSingle => self.hidden_single(regions),
+                Technique::LockedCandidates => self.locked_candidates(regions),
+                Technique::NakedPair => self.naked_subset(regions, 2),
+                Technique::HiddenPair => self.hidden_subset(regions, 2),
+                Technique::NakedTriple => self.naked_subset(regions, 3),
+                Technique::HiddenTriple => self.hidden_subset(regions, 3),
+                Technique::NakedQuad => self.naked_subset(regions, 4),
+                Technique::HiddenQuad => self.hidden_subset(regions, 4),
+                Technique::XWing => self.fish(regions, 2),
+                Technique::Swordfish => self.fish(regions, 3),
+                Technique::XYWing => self.xy_wing(regions),
+                Technique::Chain => self.chain().map(|depth| {
+                    difficulty += 10 * depth;
+                    // Each round of the search grows the loop by a link on both sides
+                    format!("odd loop of {} links", 2 * depth + 3)
+                }),
+            };
+            let Some(detail) = detail else { continue };
+            self.eliminate(regions);
+            self.check()?;
+
+            let mut placed = vec![];
+            let mut removed = vec![];
+            for v in self.board.positions() {
+                if self.board.get(&v) == True && before.get(&v) != True {
+                    placed.push(v);
+                } else if self.board.get(&v) == False && before.get(&v) == Poss {
+                    removed.push(v);
+                }
+            }
+            return Ok(Some(Step { technique, detail, placed, removed, difficulty }));
+        }
+        Ok(None)
+    }
+
+    /// Regions from every rule's houses, with duplicates merged
+    fn regions(&self) -> Vec<Region> {
+        let mut regions: Vec<Region> = vec![];
+        for con in &self.constraints {
+            for house in con.houses(&self.board) {
+                let House::Region { mut cells, full } = house else { continue };
+                cells.sort();
+                match regions.iter_mut().find(|r| r.cells == cells) {
+                    Some(r) => r.full |= full,
+                    None => regions.push(Region { cells, full }),
+                }
+            }
+        }
+        regions
+    }
+
+    /// Runs every rule once on a copy of the board, to catch a broken rule
+    fn check(&self) -> Result<(), Contradiction<Tuple3D>> {
+        let mut board = self.board.clone();
+        for con in &self.constraints {
+            con.apply(&mut board)?;
+        }
+        Ok(())
+    }
+
+    fn solved(&self, x: usize, y: usize) -> bool {
+        (0..self.board.digits()).any(|z| self.board.get(&Tuple3D::from((x, y, z))) == True)
+    }
+
+    /// Digits still possible in an unsolved cell
+    fn candidates(&self, x: usize, y: usize) -> Vec<usize> {
+        if self.solved(x, y) {
+            return vec![];
+        }
+        (0..self.board.digits()).filter(|z| self.board.get(&Tuple3D::from((x, y, *z))) == Poss).collect()
+    }
+
+    /// Removes candidates in `cells` and says whether any were there
+    fn remove(&mut self, cells: &[(usize, usize)], digits: &[usize]) -> bool {
+        let mut did = false;
+        for (x, y) in cells {
+            for z in digits {
+                let v = Tuple3D::from((*x, *y, *z));
+                if self.board.get(&v) == Poss {
+                    *self.board.getm(&v) = False;
+                    did = true;
+                }
+            }
+        }
+        did
+    }
+
+    /// Cells sharing a region with this one
+    fn peers(&self, regions: &[Region], cell: (usize, usize)) -> Vec<(usize, usize)> {
+        let mut ret = vec![];
+        for r in regions.iter().filter(|r| r.cells.contains(&cell)) {
+            for c in &r.cells {
+                if *c != cell && !ret.contains(c) {
+                    ret.push(*c);
+                }
+            }
+        }
+        ret
+    }
+
+    /// Clears other digits from solved cells and their digit from the rest of their regions
+    fn eliminate(&mut self, regions: &[Region]) {
+        for v in self.board.positions() {
+            if self.board.get(&v) != True {
+                continue;
+            }
+            let (x, y, z) = v.pos;
+            let others: Vec<usize> = (0..self.board.digits()).filter(|d| *d != z).collect();
+            self.remove(&[(x, y)], &others);
+            let peers = self.peers(regions, (x, y));
+            self.remove(&peers, &[z]);
+        }
+    }
+
+    /// Applies the rules that don't make houses, like givens and inequalities
+    fn rules(&mut self) -> Result<Option<String>, Contradiction<Tuple3D>> {
+        let mut used = 0;
+        for con in &self.constraints {
+            if con.houses(&self.board).is_empty() && con.apply(&mut self.board)? {
+                used += 1;
+            }
+        }
+        Ok(if used > 0 { Some(format!("{} applied", used)) } else { None })
+    }
+
+    /// A cell with one digit left
+    fn naked_single(&mut self) -> Option<String> {
+        for x in 0..self.board.rows() {
+            for y in 0..self.board.cols() {
+                if let [z] = self.candidates(x, y)[..] {
+                    *self.board.getm(&Tuple3D::from((x, y, z))) = True;
+                    return Some(cell_name(x, y));
+                }
+            }
+        }
+        None
+    }
+
+    /// A digit with one place left in a region that needs it
+    fn hidden_single(&mut self, regions: &[Region]) -> Option<String> {
+        for r in regions.iter().filter(|r| r.full) {
+            for z in 0..self.board.digits() {
+                let spots: Vec<&(usize, usize)> = r.cells.iter()
+                    .filter(|(x, y)| self.board.get(&Tuple3D::from((*x, *y, z))) != False)
+                    .collect();
+                if let [(x, y)] = spots[..] {
+                    if !self.solved(*x, *y) {
+                        *self.board.getm(&Tuple3D::from((*x, *y, z))) = True;
+                        return Some(format!("{} in {}", cell_name(*x, *y), region_name(&r.cells)));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// A region's places for a digit all lie in another region, so the digit
+    /// can't go anywhere else in that one
+    fn locked_candidates(&mut self, regions: &[Region]) -> Option<String> {
+        for a in regions.iter().filter(|r| r.full) {
+            for z in 0..self.board.digits() {
+                let spots: Vec<(usize, usize)> = a.cells.iter().copied()
+                    .filter(|(x, y)| self.board.get(&Tuple3D::from((*x, *y, z))) == Poss)
+                    .collect();
+                if spots.len() < 2 {
+                    continue;
+                }
+                for b in regions {
+                    if b.cells == a.cells || !spots.iter().all(|c| b.cells.contains(c)) {
+                        continue;
+                    }
+                    let rest: Vec<(usize, usize)> = b.cells.iter().copied().filter(|c| !a.cells.contains(c)).collect();
+                    if self.remove(&rest, &[z]) {
+                        return Some(format!("{} in {} and {}", cells_name(&spots), region_name(&a.cells), region_name(&b.cells)));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// `n` cells of a region with only `n` digits between them
+    fn naked_subset(&mut self, regions: &[Region], n: usize) -> Option<String> {
+        for r in regions {
+            let open: Vec<(usize, usize)> = r.cells.iter().copied()
+                .filter(|(x, y)| !self.solved(*x, *y))
+                .collect();
+            let small: Vec<(usize, usize)> = open.iter().copied()
+                .filter(|(x, y)| self.candidates(*x, *y).len() <= n)
+                .collect();
+            for cells in combinations(&small, n) {
+                let mut digits = vec![];
+                for (x, y) in &cells {
+                    for z in self.candidates(*x, *y) {
+                        if !digits.contains(&z) {
+                            digits.push(z);
+                        }
+                    }
+                }
+                if digits.len() != n {
+                    continue;
+                }
+                let rest: Vec<(usize, usize)> = open.iter().copied().filter(|c| !cells.contains(c)).collect();
+                if self.remove(&rest, &digits) {
+                    return Some(format!("{} in {}", cells_name(&cells), region_name(&r.cells)));
+                }
+            }
+        }
+        None
+    }
+
+    /// `n` digits of a region with only `n` cells between them
+    fn hidden_subset(&mut self, regions: &[Region], n: usize) -> Option<String> {
+        for r in regions.iter().filter(|r| r.full) {
+            let missing: Vec<usize> = (0..self.board.digits())
+                .filter(|z| !r.cells.iter().any(|(x, y)| self.board.get(&Tuple3D::from((*x, *y, *z))) == True))
+                .collect();
+            for digits in combinations(&missing, n) {
+                let mut cells = vec![];
+                for (x, y) in &r.cells {
+                    if digits.iter().any(|z| self.board.get(&Tuple3D::from((*x, *y, *z))) == Poss) {
+                        cells.push((*x, *y));
+                    }
+                }
+                if cells.len() != n {
+                    continue;
+                }
+                let others: Vec<usize> = (0..self.board.digits()).filter(|z| !digits.contains(z)).collect();
+                if self.remove(&cells, &others) {
+                    return Some(format!("{} in {}", cells_name(&cells), region_name(&r.cells)));
+                }
+            }
+        }
+        None
+    }
+
+    /// A digit confined to `n` columns within `n` rows, or the other way round,
+    /// can't go elsewhere in those columns
+    fn fish(&mut self, regions: &[Region], n: usize) -> Option<String> {
+        let rows = self.board.rows();
+        let cols = self.board.cols();
+        // Whole rows and columns, with the cell at index i along the line
+        let line = |flip: bool, a: usize, i: usize| if flip { (i, a) } else { (a, i) };
+        for flip in [false, true] {
+            let (lines, len) = if flip { (cols, rows) } else { (rows, cols) };
+            let is_line = |r: &Region, a: usize| r.cells.len() == len && (0..len).all(|i| r.cells.contains(&line(flip, a, i)));
+            let is_cover = |r: &Region, i: usize| r.cells.len() == lines && (0..lines).all(|a| r.cells.contains(&line(!flip, i, a)));
+            for z in 0..self.board.digits() {
+                let mut bases = vec![];
+                for a in 0..lines {
+                    if !regions.iter().any(|r| r.full && is_line(r, a)) {
+                        continue;
+                    }
+                    let spots: Vec<usize> = (0..len)
+                        .filter(|i| { let (x, y) = line(flip, a, *i); self.board.get(&Tuple3D::from((x, y, z))) == Poss })
+                        .collect();
+                    if (2..=n).contains(&spots.len()) {
+                        bases.push((a, spots));
+                    }
+                }
+                for picked in combinations(&(0..bases.len()).collect::<Vec<_>>(), n) {
+                    let mut covers = vec![];
+                    for p in &picked {
+                        for i in &bases[*p].1 {
+                            if !covers.contains(i) {
+                                covers.push(*i);
+                            }
+                        }
+                    }
+                    if covers.len() != n || !covers.iter().all(|i| regions.iter().any(|r| is_cover(r, *i))) {
+                        continue;
+                    }
+                    let base_lines: Vec<usize> = picked.iter().map(|p| bases[*p].0).collect();
+                    let rest: Vec<(usize, usize)> = covers.iter()
+                        .flat_map(|i| (0..lines).filter(|a| !base_lines.contains(a)).map(move |a| line(flip, a, *i)))
+                        .collect();
+                    if self.remove(&rest, &[z]) {
+                        let (base_name, cover_name) = if flip { ("columns", "rows") } else { ("rows", "columns") };
+                        let names = |v: &[usize]| v.iter().map(|i| (i + 1).to_string()).collect::<Vec<_>>().join(", ");
+                        covers.sort();
+                        return Some(format!("{} {} and {} {}", base_name, names(&base_lines), cover_name, names(&covers)));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// A pivot cell with digits ab seeing cells with ac and bc: whichever the pivot is,
+    /// one of those is c, so c goes from every cell seeing both
+    fn xy_wing(&mut self, regions: &[Region]) -> Option<String> {
+        let mut pairs = vec![];
+        for x in 0..self.board.rows() {
+            for y in 0..self.board.cols() {
+                let c = self.candidates(x, y);
+                if c.len() == 2 {
+                    pairs.push(((x, y), c));
+                }
+            }
+        }
+        for (pivot, ab) in &pairs {
+            let peers = self.peers(regions, *pivot);
+            for (wing1, ac) in pairs.iter().filter(|(c, _)| peers.contains(c)) {
+                let Some(c) = (if ac.contains(&ab[0]) && !ac.contains(&ab[1]) {
+                    ac.iter().copied().find(|d| *d != ab[0])
+                } else {
+                    None
+                }) else { continue };
+                for (wing2, bc) in pairs.iter().filter(|(c, _)| peers.contains(c)) {
+                    if !bc.contains(&ab[1]) || !bc.contains(&c) {
+                        continue;
+                    }
+                    let peers2 = self.peers(regions, *wing2);
+                    let both: Vec<(usize, usize)> = self.peers(regions, *wing1).into_iter()
+                        .filter(|p| peers2.contains(p))
+                        .collect();
+                    if self.remove(&both, &[c]) {
+                        return Some(format!("pivot {} with {}", cell_name(pivot.0, pivot.1), cells_name(&[*wing1, *wing2])));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// How deep the chain search had to go, if it removed anything
+    /// The number of search rounds the shortest loop found took, starting at 0
+    fn chain(&mut self) -> Option<usize> {
+        let (did, depth) = self.rem_odd_loops(None, true);
+        if did { Some(depth) } else { None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::SdkBoard;
+    use crate::puzzle_file;
+
+    fn puzzle(src: &str) -> Puzzle<Tuple3D, SdkBoard> {
+        let file = puzzle_file::parse(src).unwrap();
+        let mut game = Puzzle::init(file.board());
+        game.constraints = file.constraints();
+        game
+    }
+
+    /// Solves with techniques, checking no step places a wrong digit or removes a right one
+    fn assert_steps_follow_solution(src: &str) -> Vec<Step> {
+        let solution = puzzle(src).count_solutions(2);
+        assert_eq!(solution.examples.len(), 1, "the puzzle should be unique");
+        let solution = &solution.examples[0];
+        let mut game = puzzle(src);
+        let Ok(steps) = game.solve_steps() else { panic!("the puzzle broke a rule") };
+        for step in &steps {
+            for v in &step.placed {
+                assert!(solution.get(v) == True, "{} ({}) placed r{}c{}={}", step.technique, step.detail, v.pos.0 + 1, v.pos.1 + 1, v.pos.2 + 1);
+            }
+            for v in &step.removed {
+                assert!(solution.get(v) != True, "{} ({}) removed r{}c{}={}", step.technique, step.detail, v.pos.0 + 1, v.pos.1 + 1, v.pos.2 + 1);
+            }
+        }
+        steps
+    }
+
+    #[test]
+    fn steps_keep_the_solution() {
+        assert_steps_follow_solution(include_str!("../puzzles/normal.txt"));
+        assert_steps_follow_solution(include_str!("../puzzles/futoshiki.txt"));
+    }
+
+    #[test]
+    fn rates_a_puzzle_needing_only_singles() {
+        let Ok(rating) = puzzle(include_str!("../puzzles/normal.txt")).rate() else { panic!("the puzzle broke a rule") };
+        assert_eq!(rating.score, 52);
+        assert_eq!(rating.hardest, Some(Technique::NakedSingle));
+        assert!(rating.solved);
+    }
+
+    #[test]
+    fn rates_a_puzzle_by_its_hardest_technique() {
+        let Ok(rating) = puzzle(include_str!("../puzzles/futoshiki.txt")).rate() else { panic!("the puzzle broke a rule") };
+        assert_eq!(rating.score, 69);
+        assert_eq!(rating.hardest, Some(Technique::XWing));
+        assert!(rating.solved);
+    }
+
+    #[test]
+    fn a_puzzle_with_many_solutions_is_not_solved() {
+        let Ok(rating) = puzzle("size 4\ntype normal\ngiven r1c1 1\n").rate() else { panic!("the puzzle broke a rule") };
+        assert!(!rating.solved);
+    }
+
+    #[test]
+    fn a_stuck_solve_is_not_solved() {
+        let Ok(rating) = puzzle(include_str!("../puzzles/whispers.txt")).rate() else { panic!("the puzzle broke a rule") };
+        assert_eq!(rating.hardest, Some(Technique::LockedCandidates));
+        assert!(!rating.solved);
+    }
+
+    #[test]
+    fn chains_ignore_anti_knight_links() {
+        assert_steps_follow_solution("size 9\nantiknight\n\
+            given r1c8 9\ngiven r1c9 2\ngiven r2c1 1\ngiven r2c7 8\ngiven r5c2 9\ngiven r5c5 2\ngiven r5c6 6\n\
+            given r6c8 5\ngiven r6c9 4\ngiven r7c6 8\ngiven r7c7 5\ngiven r8c1 3\ngiven r9c4 6\ngiven r9c5 1\n");
+    }
+
+    #[test]
+    fn anti_knight_steps_keep_the_solution() {
+        let steps = assert_steps_follow_solution(include_str!("../puzzles/antiknight.txt"));
+        assert!(!steps.is_empty());
+    }
+
+    #[test]
+    fn preset_steps_keep_the_solution() {
+        assert_steps_follow_solution(include_str!("../puzzles/presets.txt"));
+    }
+
+    #[test]
+    fn chains_ignore_links_from_partial_regions() {
+        assert_steps_follow_solution("size 9\npreset argyle\n\
+            given r1c2 7\ngiven r2c2 2\ngiven r2c9 9\ngiven r5c4 1\ngiven r5c6 8\ngiven r6c1 4\ngiven r6c4 3\n\
+            given r7c2 4\ngiven r8c3 5\ngiven r8c6 9\ngiven r9c3 3\ngiven r9c6 6\ngiven r9c7 9\n");
+    }
+}